@@ -43,6 +43,45 @@ pub mod def {
         ShapeDefinition::Cuboid { dims, pos, rot }
     }
 
+    impl ShapeDefinition {
+        /// Short human readable description, with params scaled into their ranges
+        pub fn summary(&self) -> String {
+            match self {
+                ShapeDefinition::Cuboid { dims, pos, rot } => {
+                    let (w, h, d) = dims.components_scaled();
+                    let (rx, ry, rz) = rot.components_scaled();
+                    format!(
+                        "Cuboid {:.2}x{:.2}x{:.2}\nface {} ({:.2}, {:.2})\nrot ({:.2}, {:.2}, {:.2})",
+                        w,
+                        h,
+                        d,
+                        pos.0.face(),
+                        pos.1.get_scaled(),
+                        pos.2.get_scaled(),
+                        rx,
+                        ry,
+                        rz
+                    )
+                }
+            }
+        }
+    }
+
+    impl Joint {
+        /// Short human readable description, with params scaled into their ranges
+        pub fn summary(&self) -> String {
+            match self {
+                Joint::Fixed => "Fixed".to_owned(),
+                Joint::Ground => "Ground".to_owned(),
+                Joint::Rotational { torque, max_speed } => format!(
+                    "Rotational\ntorque {:.2}\nspeed {:.2}",
+                    torque.get_scaled(),
+                    max_speed.get_scaled()
+                ),
+            }
+        }
+    }
+
     impl ParamHolder for ShapeDefinition {
         fn param_count(&self) -> usize {
             match self {
@@ -83,6 +122,23 @@ pub mod params {
     #[derive(Debug, Default, Clone, Copy, new, Serialize, Deserialize)]
     pub struct FaceIndex(f64);
 
+    /// Number of faces on a cuboid
+    pub const FACE_COUNT: u32 = 6;
+
+    impl FaceIndex {
+        /// The face this param selects, in 0..FACE_COUNT
+        pub fn face(&self) -> u32 {
+            let count = f64::from(FACE_COUNT);
+            (self.get() * count).min(count - 1.0) as u32
+        }
+
+        /// Selects the given face, from the middle of its part of the range
+        pub fn set_face(&mut self, face: u32) {
+            let face = face.min(FACE_COUNT - 1);
+            self.0 = (f64::from(face) + 0.5) / f64::from(FACE_COUNT);
+        }
+    }
+
     /// x y z rotation relative to parent
     #[derive(Debug, Default, Clone, Copy, new, Serialize, Deserialize)]
     pub struct Rotation(f64);
@@ -0,0 +1,611 @@
+//! Seeding bodies from hand-designed robots, described in URDF or a subset of MuJoCo MJCF.
+//!
+//! Only box shaped parts and fixed/revolute joints are understood. All values are normalised
+//! into the range of the relevant `RangedParam`, so anything out of range is clamped. The
+//! exception is rotations, which are converted from Euler angles to the scaled axis a part is
+//! rotated by, and must already be within 0 to pi radians on each axis as any other value would
+//! end up as a different orientation.
+
+use nalgebra::{UnitQuaternion, Vector3};
+use roxmltree::{Document, Node as XmlNode};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+
+use body::def::{self, ParamHolder, RangedParam};
+use body::params::*;
+use tree::{BodyTree, NodeIndex};
+use Coord;
+
+type Vec3 = (Coord, Coord, Coord);
+
+#[derive(Debug)]
+pub enum ImportError {
+    Xml(String),
+    MissingElement(&'static str),
+    MissingAttribute(&'static str, &'static str),
+    BadNumber(String),
+    UnsupportedGeometry(String),
+    UnsupportedJoint(String),
+    UnknownLink(String),
+    NoRoot,
+    MultipleRoots,
+    MultipleParents(String),
+    RotationOutOfRange(Coord),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Xml(e) => write!(f, "bad xml: {}", e),
+            ImportError::MissingElement(e) => write!(f, "missing <{}> element", e),
+            ImportError::MissingAttribute(e, a) => write!(f, "<{}> missing attribute '{}'", e, a),
+            ImportError::BadNumber(s) => write!(f, "bad number(s) '{}'", s),
+            ImportError::UnsupportedGeometry(s) => write!(f, "unsupported geometry in '{}'", s),
+            ImportError::UnsupportedJoint(s) => write!(f, "unsupported joint type '{}'", s),
+            ImportError::UnknownLink(s) => write!(f, "unknown link '{}'", s),
+            ImportError::NoRoot => write!(f, "no root link"),
+            ImportError::MultipleRoots => write!(f, "more than one root link"),
+            ImportError::MultipleParents(s) => write!(f, "link '{}' has more than one parent", s),
+            ImportError::RotationOutOfRange(r) => write!(f, "rotation {} outside 0..=pi", r),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+pub type ImportResult<T> = Result<T, ImportError>;
+
+/// A part in the source description, with everything in real units.
+struct Part {
+    /// Half extents of the box
+    half_extents: Vec3,
+    /// Offset from the parent part's centre, in the parent's frame
+    offset: Vec3,
+    rotation: Vec3,
+    joint: JointDesc,
+    children: Vec<usize>,
+}
+
+enum JointDesc {
+    Fixed,
+    Revolute {
+        torque: Option<Coord>,
+        max_speed: Option<Coord>,
+    },
+}
+
+/// Parses a URDF robot made of box links connected by fixed, revolute or continuous joints.
+pub fn from_urdf(xml: &str) -> ImportResult<BodyTree> {
+    let doc = Document::parse(xml).map_err(|e| ImportError::Xml(e.to_string()))?;
+    let robot = doc.root_element();
+    if robot.tag_name().name() != "robot" {
+        return Err(ImportError::MissingElement("robot"));
+    }
+
+    let links: Vec<XmlNode> = robot
+        .children()
+        .filter(|n| n.has_tag_name("link"))
+        .collect();
+    let mut names = Vec::with_capacity(links.len());
+    let mut parts = Vec::with_capacity(links.len());
+    for link in &links {
+        names.push(required_attr(link, "link", "name")?);
+        parts.push(Part {
+            half_extents: urdf_box_half_extents(link)?,
+            offset: (0.0, 0.0, 0.0),
+            rotation: (0.0, 0.0, 0.0),
+            joint: JointDesc::Fixed,
+            children: Vec::new(),
+        });
+    }
+
+    let find = |name: &str| {
+        names
+            .iter()
+            .position(|n| *n == name)
+            .ok_or_else(|| ImportError::UnknownLink(name.to_owned()))
+    };
+
+    let mut has_parent = vec![false; parts.len()];
+    for joint in robot.children().filter(|n| n.has_tag_name("joint")) {
+        let parent = child_attr(&joint, "parent", "link")?;
+        let child = child_attr(&joint, "child", "link")?;
+        let (parent, child) = (find(parent)?, find(child)?);
+        // also catches cycles, as some link in one must have a second parent to be reachable
+        if has_parent[child] {
+            return Err(ImportError::MultipleParents(names[child].to_owned()));
+        }
+
+        let kind = required_attr(&joint, "joint", "type")?;
+        let desc = match kind {
+            "fixed" => JointDesc::Fixed,
+            "revolute" | "continuous" => {
+                let limit = joint.children().find(|n| n.has_tag_name("limit"));
+                let limit_attr = |name: &str| match limit.and_then(|l| l.attribute(name)) {
+                    Some(s) => parse_number(s).map(Some),
+                    None => Ok(None),
+                };
+                JointDesc::Revolute {
+                    torque: limit_attr("effort")?,
+                    max_speed: limit_attr("velocity")?,
+                }
+            }
+            _ => return Err(ImportError::UnsupportedJoint(kind.to_owned())),
+        };
+
+        if let Some(origin) = joint.children().find(|n| n.has_tag_name("origin")) {
+            if let Some(xyz) = origin.attribute("xyz") {
+                parts[child].offset = parse_vec3(xyz)?;
+            }
+            if let Some(rpy) = origin.attribute("rpy") {
+                // about the fixed x, y then z axes
+                let (r, p, y) = parse_vec3(rpy)?;
+                parts[child].rotation = check_rotation(UnitQuaternion::from_euler_angles(r, p, y))?;
+            }
+        }
+
+        parts[child].joint = desc;
+        parts[parent].children.push(child);
+        has_parent[child] = true;
+    }
+
+    let root = single_root(&has_parent)?;
+    Ok(build_tree(&parts, root))
+}
+
+/// Parses the first top level body in an MJCF `<worldbody>`. Each body must contain a box geom,
+/// and is attached to its parent by a hinge if it has one, otherwise fixed.
+pub fn from_mjcf(xml: &str) -> ImportResult<BodyTree> {
+    let doc = Document::parse(xml).map_err(|e| ImportError::Xml(e.to_string()))?;
+    let mujoco = doc.root_element();
+    if mujoco.tag_name().name() != "mujoco" {
+        return Err(ImportError::MissingElement("mujoco"));
+    }
+
+    // angles are in degrees unless stated otherwise
+    let radians = mujoco
+        .children()
+        .find(|n| n.has_tag_name("compiler"))
+        .and_then(|c| c.attribute("angle"))
+        .map_or(false, |a| a == "radian");
+
+    let worldbody = mujoco
+        .children()
+        .find(|n| n.has_tag_name("worldbody"))
+        .ok_or(ImportError::MissingElement("worldbody"))?;
+    let root = worldbody
+        .children()
+        .find(|n| n.has_tag_name("body"))
+        .ok_or(ImportError::NoRoot)?;
+
+    let mut parts = Vec::new();
+    mjcf_body(&root, radians, &mut parts)?;
+    Ok(build_tree(&parts, 0))
+}
+
+fn mjcf_body(body: &XmlNode, radians: bool, parts: &mut Vec<Part>) -> ImportResult<usize> {
+    let geom = body
+        .children()
+        .find(|n| n.has_tag_name("geom"))
+        .ok_or(ImportError::MissingElement("geom"))?;
+    if geom.attribute("type") != Some("box") {
+        return Err(ImportError::UnsupportedGeometry(
+            body.attribute("name").unwrap_or("body").to_owned(),
+        ));
+    }
+
+    let joint = match body.children().find(|n| n.has_tag_name("joint")) {
+        None => JointDesc::Fixed,
+        Some(j) => match j.attribute("type").unwrap_or("hinge") {
+            "hinge" => JointDesc::Revolute {
+                torque: None,
+                max_speed: None,
+            },
+            "free" => JointDesc::Fixed, // only meaningful on the root
+            other => return Err(ImportError::UnsupportedJoint(other.to_owned())),
+        },
+    };
+
+    // about the x, y then z axes as they rotate, the default sequence
+    let rotation = match body.attribute("euler") {
+        Some(s) => {
+            let (x, y, z) = parse_vec3(s)?;
+            let (x, y, z) = if radians {
+                (x, y, z)
+            } else {
+                (x.to_radians(), y.to_radians(), z.to_radians())
+            };
+            check_rotation(
+                UnitQuaternion::from_axis_angle(&Vector3::x_axis(), x)
+                    * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), y)
+                    * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), z),
+            )?
+        }
+        None => (0.0, 0.0, 0.0),
+    };
+
+    let index = parts.len();
+    parts.push(Part {
+        // mjcf box sizes are already half extents
+        half_extents: parse_vec3(required_attr(&geom, "geom", "size")?)?,
+        offset: body
+            .attribute("pos")
+            .map_or(Ok((0.0, 0.0, 0.0)), parse_vec3)?,
+        rotation,
+        joint,
+        children: Vec::new(),
+    });
+
+    for child in body.children().filter(|n| n.has_tag_name("body")) {
+        let child = mjcf_body(&child, radians, parts)?;
+        parts[index].children.push(child);
+    }
+
+    Ok(index)
+}
+
+fn urdf_box_half_extents(link: &XmlNode) -> ImportResult<Vec3> {
+    let name = link.attribute("name").unwrap_or("link");
+
+    // prefer collision geometry, falling back to visual
+    let geometry = ["collision", "visual"]
+        .iter()
+        .filter_map(|tag| link.children().find(|n| n.has_tag_name(*tag)))
+        .filter_map(|n| n.children().find(|n| n.has_tag_name("geometry")))
+        .next()
+        .ok_or_else(|| ImportError::UnsupportedGeometry(name.to_owned()))?;
+
+    let size = geometry
+        .children()
+        .find(|n| n.has_tag_name("box"))
+        .ok_or_else(|| ImportError::UnsupportedGeometry(name.to_owned()))?;
+
+    let (x, y, z) = parse_vec3(required_attr(&size, "box", "size")?)?;
+    Ok((x / 2.0, y / 2.0, z / 2.0))
+}
+
+fn single_root(has_parent: &[bool]) -> ImportResult<usize> {
+    let mut roots = has_parent.iter().enumerate().filter(|(_, p)| !**p);
+    match (roots.next(), roots.next()) {
+        (Some((i, _)), None) => Ok(i),
+        (None, _) => Err(ImportError::NoRoot),
+        (Some(_), Some(_)) => Err(ImportError::MultipleRoots),
+    }
+}
+
+fn build_tree(parts: &[Part], root: usize) -> BodyTree {
+    let shape = shape_def(&parts[root], None);
+    let mut tree = BodyTree::with_root(shape);
+    let tree_root = tree.root();
+    add_children(&mut tree, parts, root, tree_root);
+    tree
+}
+
+fn add_children(tree: &mut BodyTree, parts: &[Part], current: usize, node: NodeIndex) {
+    let parent = &parts[current];
+    for &child in &parent.children {
+        let part = &parts[child];
+        let shape = shape_def(part, Some(parent));
+        let child_node = tree.add_child(node, shape, joint_def(&part.joint));
+        add_children(tree, parts, child, child_node);
+    }
+}
+
+fn shape_def(part: &Part, parent: Option<&Part>) -> def::ShapeDefinition {
+    let (w, h, d) = part.half_extents;
+    let (rx, ry, rz) = part.rotation;
+    let mut shape = def::new_cuboid((0.0, 0.0, 0.0), (0.0, 0.0, 0.0), (0.0, 0.0, 0.0));
+
+    match shape {
+        def::ShapeDefinition::Cuboid {
+            ref mut dims,
+            ref mut pos,
+            ref mut rot,
+        } => {
+            for (i, val) in [w, h, d].iter().enumerate() {
+                dims.get_param(i).set_scaled(*val);
+            }
+            for (i, val) in [rx, ry, rz].iter().enumerate() {
+                rot.get_param(i).set_scaled(*val);
+            }
+            if let Some(parent) = parent {
+                let (face, f1, f2) =
+                    face_placement(part.offset, parent.half_extents, part.half_extents);
+                pos.0.set_face(face);
+                pos.1.set_scaled(f1);
+                pos.2.set_scaled(f2);
+            }
+        }
+    }
+
+    shape
+}
+
+fn joint_def(joint: &JointDesc) -> def::Joint {
+    match joint {
+        JointDesc::Fixed => def::Joint::Fixed,
+        JointDesc::Revolute { torque, max_speed } => {
+            // missing limits default to the middle of the range
+            let mut t = Torque::new(0.5);
+            let mut s = MaxSpeed::new(0.5);
+            if let Some(torque) = torque {
+                t.set_scaled(*torque);
+            }
+            if let Some(max_speed) = max_speed {
+                s.set_scaled(*max_speed);
+            }
+            def::Joint::Rotational {
+                torque: t,
+                max_speed: s,
+            }
+        }
+    }
+}
+
+/// Picks the parent face the offset points most towards, and the (unscaled) coordinates on
+/// that face. This is the inverse of the placement done when realising a body.
+fn face_placement(offset: Vec3, parent: Vec3, me: Vec3) -> (u32, Coord, Coord) {
+    let (ox, oy, oz) = offset;
+    let nx = parent.0.min(me.0);
+    let ny = parent.1.min(me.1);
+    let nz = parent.2.min(me.2);
+
+    let (ax, ay, az) = (
+        ox.abs() / parent.0,
+        oy.abs() / parent.1,
+        oz.abs() / parent.2,
+    );
+    if ay >= ax && ay >= az {
+        let face = if oy < 0.0 { 0 } else { 1 };
+        (face, ox / nx, oz / nz)
+    } else if ax >= az {
+        let face = if ox < 0.0 { 2 } else { 3 };
+        (face, oy / ny, oz / nz)
+    } else {
+        let face = if oz > 0.0 { 4 } else { 5 };
+        (face, ox / nx, oy / ny)
+    }
+}
+
+fn required_attr<'a>(
+    node: &XmlNode<'a, '_>,
+    element: &'static str,
+    attr: &'static str,
+) -> ImportResult<&'a str> {
+    node.attribute(attr)
+        .ok_or(ImportError::MissingAttribute(element, attr))
+}
+
+fn child_attr<'a>(
+    node: &XmlNode<'a, '_>,
+    child: &'static str,
+    attr: &'static str,
+) -> ImportResult<&'a str> {
+    let child_node = node
+        .children()
+        .find(|n| n.has_tag_name(child))
+        .ok_or(ImportError::MissingElement(child))?;
+    required_attr(&child_node, child, attr)
+}
+
+fn parse_number(s: &str) -> ImportResult<Coord> {
+    s.trim()
+        .parse()
+        .map_err(|_| ImportError::BadNumber(s.to_owned()))
+}
+
+/// The scaled axis of the rotation, which must fit the range of a `Rotation` as it is
+fn check_rotation(rotation: UnitQuaternion<Coord>) -> ImportResult<Vec3> {
+    let axis = rotation.scaled_axis();
+    match axis.iter().find(|r| !(0.0..=PI).contains(*r)) {
+        Some(r) => Err(ImportError::RotationOutOfRange(*r)),
+        None => Ok((axis.x, axis.y, axis.z)),
+    }
+}
+
+fn parse_vec3(s: &str) -> ImportResult<Vec3> {
+    let nums = s
+        .split_whitespace()
+        .map(parse_number)
+        .collect::<ImportResult<Vec<_>>>()?;
+    match nums.as_slice() {
+        [x, y, z] => Ok((*x, *y, *z)),
+        _ => Err(ImportError::BadNumber(s.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use petgraph::visit::EdgeRef;
+
+    const URDF: &str = r#"
+        <robot name="test">
+            <link name="base">
+                <collision><geometry><box size="2 0.5 1"/></geometry></collision>
+            </link>
+            <link name="arm">
+                <visual><geometry><box size="0.2 1 0.2"/></geometry></visual>
+            </link>
+            <link name="hand">
+                <visual><geometry><box size="0.2 0.2 0.2"/></geometry></visual>
+            </link>
+            <joint name="shoulder" type="revolute">
+                <parent link="base"/>
+                <child link="arm"/>
+                <origin xyz="0 0.75 0" rpy="0 0 0"/>
+                <limit effort="3.5" velocity="100"/>
+            </joint>
+            <joint name="wrist" type="fixed">
+                <parent link="arm"/>
+                <child link="hand"/>
+                <origin xyz="-0.2 0 0"/>
+            </joint>
+        </robot>
+    "#;
+
+    const MJCF: &str = r#"
+        <mujoco>
+            <compiler angle="radian"/>
+            <worldbody>
+                <body name="torso" pos="0 1 0">
+                    <joint type="free"/>
+                    <geom type="box" size="1 0.25 0.5"/>
+                    <body name="leg" pos="0 -0.5 0" euler="0 0 1">
+                        <joint type="hinge" axis="1 0 0"/>
+                        <geom type="box" size="0.1 0.5 0.1"/>
+                    </body>
+                </body>
+            </worldbody>
+        </mujoco>
+    "#;
+
+    #[test]
+    fn urdf() {
+        let tree = from_urdf(URDF).expect("failed to import");
+        assert_eq!(tree.node_count(), 3);
+
+        let root = tree.root();
+        let arm_edge = tree.get_children(root).next().expect("no arm");
+        match arm_edge.weight() {
+            def::Joint::Rotational { torque, max_speed } => {
+                assert!((torque.get_scaled() - 3.5).abs() < 0.001);
+                assert!((max_speed.get_scaled() - 10.0).abs() < 0.001); // clamped
+            }
+            j => panic!("unexpected joint {:?}", j),
+        }
+
+        let arm = arm_edge.source();
+        match *tree.shape(arm) {
+            def::ShapeDefinition::Cuboid { dims, pos, .. } => {
+                let (w, h, d) = dims.components_scaled();
+                assert!((w - 0.1).abs() < 0.001);
+                assert!((h - 0.5).abs() < 0.001);
+                assert!((d - 0.1).abs() < 0.001);
+                assert_eq!(pos.0.face(), 1); // top
+            }
+        }
+
+        let hand_edge = tree.get_children(arm).next().expect("no hand");
+        match hand_edge.weight() {
+            def::Joint::Fixed => {}
+            j => panic!("unexpected joint {:?}", j),
+        }
+        let hand = tree.shape(hand_edge.source());
+        match *hand {
+            def::ShapeDefinition::Cuboid { pos, .. } => assert_eq!(pos.0.face(), 2), // back
+        }
+    }
+
+    #[test]
+    fn urdf_errors() {
+        match from_urdf("<robot><link name=\"a\"/></robot>") {
+            Err(ImportError::UnsupportedGeometry(_)) => {}
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+
+        let two_roots = r#"
+            <robot>
+                <link name="a"><visual><geometry><box size="1 1 1"/></geometry></visual></link>
+                <link name="b"><visual><geometry><box size="1 1 1"/></geometry></visual></link>
+            </robot>"#;
+        match from_urdf(two_roots) {
+            Err(ImportError::MultipleRoots) => {}
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+
+        match from_urdf(&URDF.replace("rpy=\"0 0 0\"", "rpy=\"0 0 -1.5\"")) {
+            Err(ImportError::RotationOutOfRange(r)) => assert_eq!(r, -1.5),
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+    }
+
+    fn rotation_of_first_child(tree: &BodyTree) -> Vector3<Coord> {
+        let child = tree.get_children(tree.root()).next().expect("no child");
+        let shape = tree.shape(child.source());
+        match *shape {
+            def::ShapeDefinition::Cuboid { rot, .. } => {
+                let (x, y, z) = rot.components_scaled();
+                Vector3::new(x, y, z)
+            }
+        }
+    }
+
+    #[test]
+    fn two_axis_rotations() {
+        let urdf = URDF.replace("rpy=\"0 0 0\"", "rpy=\"0.5 0 0.5\"");
+        let tree = from_urdf(&urdf).expect("failed to import");
+        let expected = UnitQuaternion::from_euler_angles(0.5, 0.0, 0.5).scaled_axis();
+        let actual = rotation_of_first_child(&tree);
+        assert!((actual - expected).norm() < 1e-6);
+        // not just the angles copied across
+        assert!(actual.y > 0.1);
+
+        let mjcf = MJCF.replace("euler=\"0 0 1\"", "euler=\"0.5 0.5 0\"");
+        let tree = from_mjcf(&mjcf).expect("failed to import");
+        let expected = (UnitQuaternion::from_axis_angle(&Vector3::x_axis(), 0.5)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.5))
+        .scaled_axis();
+        let actual = rotation_of_first_child(&tree);
+        assert!((actual - expected).norm() < 1e-6);
+        assert!(actual.z > 0.1);
+    }
+
+    /// Box links a to d joined by the given (parent, child) pairs
+    fn linked(joints: &[(&str, &str)]) -> String {
+        let mut xml = String::from("<robot>");
+        for name in &["a", "b", "c", "d"] {
+            xml += &format!(
+                "<link name=\"{}\"><visual><geometry><box size=\"1 1 1\"/></geometry></visual></link>",
+                name
+            );
+        }
+        for (parent, child) in joints {
+            xml += &format!(
+                "<joint type=\"fixed\"><parent link=\"{}\"/><child link=\"{}\"/></joint>",
+                parent, child
+            );
+        }
+        xml + "</robot>"
+    }
+
+    #[test]
+    fn urdf_multiple_parents() {
+        let cycle = linked(&[("a", "b"), ("b", "c"), ("c", "b"), ("a", "d")]);
+        match from_urdf(&cycle) {
+            Err(ImportError::MultipleParents(ref name)) if name == "b" => {}
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+
+        let diamond = linked(&[("a", "b"), ("a", "c"), ("b", "d"), ("c", "d")]);
+        match from_urdf(&diamond) {
+            Err(ImportError::MultipleParents(ref name)) if name == "d" => {}
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+
+        let tree = linked(&[("a", "b"), ("a", "c"), ("b", "d")]);
+        assert_eq!(from_urdf(&tree).expect("failed to import").node_count(), 4);
+    }
+
+    #[test]
+    fn mjcf() {
+        let tree = from_mjcf(MJCF).expect("failed to import");
+        assert_eq!(tree.node_count(), 2);
+
+        let root = tree.root();
+        let leg_edge = tree.get_children(root).next().expect("no leg");
+        match leg_edge.weight() {
+            def::Joint::Rotational { .. } => {}
+            j => panic!("unexpected joint {:?}", j),
+        }
+        let leg = tree.shape(leg_edge.source());
+        match *leg {
+            def::ShapeDefinition::Cuboid { pos, rot, .. } => {
+                assert_eq!(pos.0.face(), 0); // bottom
+                let (_, _, rz) = rot.components_scaled();
+                assert!((rz - 1.0).abs() < 0.001);
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@ extern crate nalgebra;
 extern crate ncollide3d;
 extern crate petgraph;
 extern crate rand;
+extern crate roxmltree;
 
 extern crate generic_mutation;
 
@@ -17,9 +18,36 @@ extern crate serde_derive;
 extern crate derive_new;
 
 pub mod body;
+pub mod import;
 pub mod serialise;
 pub mod tree;
 
 pub type Coord = f64;
 
 pub type Population = Vec<tree::BodyTree>;
+
+/// Wraps a value into `min..max`, such as an angle into a single turn
+pub fn wrap(value: Coord, min: Coord, max: Coord) -> Coord {
+    let range = max - min;
+    let wrapped = (value - min) % range;
+    if wrapped < 0.0 {
+        wrapped + range + min
+    } else {
+        wrapped + min
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::wrap;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn wrapping() {
+        assert!((wrap(0.1, -PI, PI) - 0.1).abs() < 1e-9);
+        assert!((wrap(2.0 * PI - 0.1, -PI, PI) + 0.1).abs() < 1e-9);
+        assert!((wrap(-2.0 * PI + 0.1, -PI, PI) - 0.1).abs() < 1e-9);
+        assert!((wrap(PI + 0.1, 0.0, PI) - 0.1).abs() < 1e-9);
+        assert!((wrap(-0.1, 0.0, PI) - (PI - 0.1)).abs() < 1e-9);
+    }
+}
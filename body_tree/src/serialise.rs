@@ -1,27 +1,39 @@
 use super::Population;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
 pub fn load<P: Into<PathBuf>>(path: P) -> Population {
+    load_json(path)
+}
+
+pub fn save<P: Into<PathBuf>>(path: P, pop: &Population) {
+    save_json(path, pop)
+}
+
+/// Same as `load`, for anything else containing trees
+pub fn load_json<P: Into<PathBuf>, T: DeserializeOwned>(path: P) -> T {
     let path = path.into();
     let f = File::open(&path).expect(&format!("Failed to read file {:?}", path));
     deserialise(f)
 }
 
-pub fn save<P: Into<PathBuf>>(path: P, pop: &Population) {
+/// Same as `save`, for anything else containing trees
+pub fn save_json<P: Into<PathBuf>, T: Serialize>(path: P, value: &T) {
     let path = path.into();
     let f = File::create(&path).expect(&format!("Failed to create file {:?}", path));
-    serialise(f, pop)
+    serialise(f, value)
 }
 
-fn deserialise<R: Read>(reader: R) -> Population {
+fn deserialise<R: Read, T: DeserializeOwned>(reader: R) -> T {
     serde_json::from_reader(reader).expect("Failed to deserialise")
 }
 
-fn serialise<W: Write>(writer: W, pop: &Population) {
-    serde_json::to_writer(writer, &pop).expect("Failed to serialise");
+fn serialise<W: Write, T: Serialize>(writer: W, value: &T) {
+    serde_json::to_writer(writer, value).expect("Failed to serialise");
 }
 
 #[cfg(test)]
@@ -29,24 +41,19 @@ mod tests {
     use super::{deserialise, serialise};
     use body::def::*;
     use body::params::*;
-    use std::cell::RefCell;
     use std::io::Cursor;
-    use std::rc::Rc;
     use tree::*;
+    use Population;
 
     #[test]
     fn save_and_load() {
         let tree = {
             let root_shape = new_cuboid((0.5, 2.0, 1.0), (0.0, 0.0, 0.0), (0.0, 0.0, 0.0));
-            let mut t = BodyTree::with_root(Rc::new(RefCell::new(root_shape)));
+            let mut t = BodyTree::with_root(root_shape);
             let root = t.root();
             t.add_child(
                 root,
-                Rc::new(RefCell::new(new_cuboid(
-                    (1.0, 3.0, 0.1),
-                    (0.0, 2.0, 0.0),
-                    (1.2, 2.0, 1.0),
-                ))),
+                new_cuboid((1.0, 3.0, 0.1), (0.0, 2.0, 0.0), (1.2, 2.0, 1.0)),
                 Joint::Rotational {
                     max_speed: MaxSpeed::new(0.8),
                     torque: Torque::new(0.8),
@@ -62,7 +69,7 @@ mod tests {
         let _serialised = serialise(&mut cursor, &pop);
         cursor.set_position(0);
         println!("{}", String::from_utf8(cursor.get_ref().to_vec()).unwrap());
-        let deserialised = deserialise(&mut cursor);
+        let deserialised: Population = deserialise(&mut cursor);
 
         assert_eq!(pop.len(), deserialised.len());
     }
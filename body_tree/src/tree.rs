@@ -1,22 +1,21 @@
 use petgraph;
 pub use petgraph::graph::NodeIndex;
-use petgraph::visit::EdgeRef;
+pub use petgraph::visit::EdgeRef;
 use rand::{self, Rng, RngCore};
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::fmt::Write;
 
 use body::def;
 use generic_mutation;
 
 pub use self::gen::grow_random_tree;
 
-type Node = Rc<RefCell<def::ShapeDefinition>>;
+type Node = def::ShapeDefinition;
 type Edge = def::Joint;
 type GraphSize = petgraph::graph::DefaultIx;
 type Tree = petgraph::Graph<Node, Edge, petgraph::Directed, GraphSize>;
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct BodyTree {
     tree: Tree,
     root: NodeIndex,
@@ -33,6 +32,18 @@ impl BodyTree {
         self.root
     }
 
+    pub fn node_count(&self) -> usize {
+        self.tree.node_count()
+    }
+
+    pub fn shape(&self, node: NodeIndex) -> &def::ShapeDefinition {
+        &self.tree[node]
+    }
+
+    pub fn shape_mut(&mut self, node: NodeIndex) -> &mut def::ShapeDefinition {
+        &mut self.tree[node]
+    }
+
     pub fn add_child(&mut self, parent: NodeIndex, child: Node, edge: Edge) -> NodeIndex {
         // TODO limit children count at all?
         let new_node = self.tree.add_node(child);
@@ -40,6 +51,19 @@ impl BodyTree {
         new_node
     }
 
+    /// Number of nodes on the longest path down from the root, so 1 for a lone root
+    pub fn depth(&self) -> usize {
+        self.depth_below(self.root)
+    }
+
+    fn depth_below(&self, node: NodeIndex) -> usize {
+        1 + self
+            .get_children(node)
+            .map(|edge| self.depth_below(edge.source()))
+            .max()
+            .unwrap_or(0)
+    }
+
     fn children_count(&self, parent: NodeIndex) -> usize {
         self.get_children(parent).count()
     }
@@ -62,7 +86,7 @@ impl BodyTree {
         let node = &self.tree[current];
 
         // create shape for self
-        let new_node = realiser.new_shape(&node.borrow(), parent_handle, parent_joint);
+        let new_node = realiser.new_shape(node, parent_handle, parent_joint);
 
         // children
         for edge_ref in self.get_children(current) {
@@ -77,9 +101,44 @@ impl BodyTree {
         self.actually_recurse(self.root, handle, &joint, realiser);
     }
 
+    /// Renders the tree in graphviz DOT format, with edges pointing from parent to child
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph body {{").unwrap();
+        writeln!(dot, "    node [shape=box];").unwrap();
+
+        for idx in self.tree.node_indices() {
+            let node = &self.tree[idx];
+            let mut label = format!("#{}\n{}", idx.index(), node.summary());
+            if idx == self.root {
+                label.insert_str(0, "root ");
+            }
+            writeln!(
+                dot,
+                "    {} [label=\"{}\"];",
+                idx.index(),
+                escape_dot(&label)
+            ).unwrap();
+        }
+
+        for edge in self.tree.edge_references() {
+            // edges are stored child -> parent
+            writeln!(
+                dot,
+                "    {} -> {} [label=\"{}\"];",
+                edge.target().index(),
+                edge.source().index(),
+                escape_dot(&edge.weight().summary())
+            ).unwrap();
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
     fn actually_mutate<MG: generic_mutation::MutationGen>(&mut self, mut mut_gen: MG) {
         for node in self.tree.node_weights_mut() {
-            generic_mutation::mutate(node.clone(), &mut mut_gen);
+            generic_mutation::mutate(node, &mut mut_gen);
         }
     }
 
@@ -94,6 +153,10 @@ impl BodyTree {
     }
 }
 
+fn escape_dot(label: &str) -> String {
+    label.replace('"', "\\\"").replace('\n', "\\n")
+}
+
 struct RandomMutationGen<'a> {
     rng: &'a mut RngCore,
     rate: f64,
@@ -135,12 +198,11 @@ mod gen {
     }
 
     fn random_node() -> Node {
-        let shape = def::new_cuboid(
+        def::new_cuboid(
             (0.04, 0.7, 0.04), // prefer sticks
             (gen(), gen(), gen()),
             (gen(), gen(), gen()),
-        );
-        Rc::new(RefCell::new(shape))
+        )
     }
 
     fn random_edge() -> Edge {
@@ -174,7 +236,7 @@ mod gen {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use body::def;
+    use body::{def, params};
 
     struct DebugRealiser {
         last_node: i64,
@@ -209,17 +271,41 @@ mod tests {
     }
 
     fn shape() -> Node {
-        Rc::new(RefCell::new(def::new_cuboid(
-            (5.0, 5.0, 5.0),
-            (0.0, 0.0, 0.0),
-            (0.0, 0.0, 0.0),
-        )))
+        def::new_cuboid((5.0, 5.0, 5.0), (0.0, 0.0, 0.0), (0.0, 0.0, 0.0))
     }
 
     fn joint() -> Edge {
         def::Joint::Fixed
     }
 
+    #[test]
+    fn dot() {
+        let mut tree = BodyTree::with_root(shape());
+        let root = tree.root();
+        let child = tree.add_child(root, shape(), joint());
+        tree.add_child(
+            child,
+            shape(),
+            def::Joint::Rotational {
+                torque: params::Torque::new(0.0),
+                max_speed: params::MaxSpeed::new(1.0),
+            },
+        );
+
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph body {"));
+        assert!(dot.contains("0 [label=\"root #0\\nCuboid"));
+        assert!(dot.contains("0 -> 1 [label=\"Fixed\"]"));
+        assert!(dot.contains("1 -> 2 [label=\"Rotational\\ntorque 2.00\\nspeed 10.00\"]"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn thread_safe() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<BodyTree>();
+    }
+
     #[test]
     fn realiser() {
         let mut tree = BodyTree::with_root(shape());
@@ -234,4 +320,18 @@ mod tests {
         };
         tree.realise(&mut r);
     }
+
+    #[test]
+    fn depth() {
+        let mut tree = BodyTree::with_root(shape());
+        assert_eq!(tree.depth(), 1);
+
+        let root = tree.root();
+        let child = tree.add_child(root, shape(), joint());
+        tree.add_child(root, shape(), joint());
+        assert_eq!(tree.depth(), 2);
+
+        tree.add_child(child, shape(), joint());
+        assert_eq!(tree.depth(), 3);
+    }
 }
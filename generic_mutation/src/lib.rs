@@ -1,6 +1,4 @@
-use std::cell::RefCell;
 use std::ops::AddAssign;
-use std::rc::Rc;
 
 #[cfg(feature = "serialize")]
 extern crate serde;
@@ -10,13 +8,6 @@ extern crate serde;
 extern crate serde_derive;
 
 pub type Param = f64;
-pub type ParamHolderRef<P> = Rc<RefCell<P>>;
-
-#[derive(Debug)]
-pub struct GenericParams<PH: ParamHolder> {
-    owner: Rc<RefCell<PH>>,
-    n: usize,
-}
 
 /// An entity with multiple parameters.
 pub trait ParamHolder {
@@ -39,6 +30,13 @@ pub trait RangedParam {
         let (min, max) = self.range();
         (max - min) * self.get() + min
     }
+
+    /// Inverse of `get_scaled`, clamping to the range
+    fn set_scaled(&mut self, value: Param) {
+        let (min, max) = self.range();
+        let unscaled = (value - min) / (max - min);
+        *self.get_mut() = unscaled.max(0.0).min(1.0);
+    }
 }
 
 /// Collection of related parameters in multiple dimensions.
@@ -88,13 +86,6 @@ pub trait MutationGen {
     fn gen(&mut self) -> Param;
 }
 
-impl<PH: ParamHolder> GenericParams<PH> {
-    fn new(holder: Rc<RefCell<PH>>) -> Self {
-        let n = holder.borrow().param_count();
-        Self { owner: holder, n }
-    }
-}
-
 impl<'a> AddAssign<Param> for &'a mut RangedParam {
     fn add_assign(&mut self, rhs: Param) {
         let clamped = {
@@ -111,12 +102,9 @@ impl<'a> AddAssign<Param> for &'a mut RangedParam {
     }
 }
 
-pub fn mutate<P: ParamHolder, MG: MutationGen>(param_holder: ParamHolderRef<P>, mut_gen: &mut MG) {
-    let params = GenericParams::new(param_holder);
-
-    for i in 0..params.n {
-        let mut holder = params.owner.borrow_mut();
-        let mut p: &mut RangedParam = holder.get_param(i);
+pub fn mutate<P: ParamHolder, MG: MutationGen>(param_holder: &mut P, mut_gen: &mut MG) {
+    for i in 0..param_holder.param_count() {
+        let mut p: &mut RangedParam = param_holder.get_param(i);
         p += mut_gen.gen();
     }
 }
@@ -168,27 +156,41 @@ mod tests {
 
     #[test]
     fn test_mutate() {
-        let holder = Rc::new(RefCell::new(TestHolder {
+        let mut holder = TestHolder {
             x: TestParam { 0: 0.0 },
-        }));
-        mutate(holder.clone(), &mut ConstGen { 0: 0.5 });
+        };
+        mutate(&mut holder, &mut ConstGen { 0: 0.5 });
 
         let expected = 10.0; // 20.0 * 0.5
-        let diff = (holder.borrow().x.get_scaled() - expected).abs();
+        let diff = (holder.x.get_scaled() - expected).abs();
         assert!(diff < 0.001);
     }
 
     #[test]
     fn test_clamp() {
-        let holder = Rc::new(RefCell::new(TestHolder {
+        let mut holder = TestHolder {
             x: TestParam { 0: 0.0 },
-        }));
-        mutate(holder.clone(), &mut ConstGen { 0: -0.5 });
-        assert!(holder.borrow().x.get_scaled() < 0.001);
+        };
+        mutate(&mut holder, &mut ConstGen { 0: -0.5 });
+        assert!(holder.x.get_scaled() < 0.001);
 
         // should be equal to max
-        mutate(holder.clone(), &mut ConstGen { 0: 1.5 });
-        assert!((holder.borrow().x.get_scaled() - 20.0).abs() < 0.001);
+        mutate(&mut holder, &mut ConstGen { 0: 1.5 });
+        assert!((holder.x.get_scaled() - 20.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_set_scaled() {
+        let mut p = TestParam(0.0);
+        p.set_scaled(5.0);
+        assert!((p.get() - 0.25).abs() < 0.001);
+        assert!((p.get_scaled() - 5.0).abs() < 0.001);
+
+        p.set_scaled(-3.0);
+        assert!(p.get().abs() < 0.001);
+
+        p.set_scaled(30.0);
+        assert!((p.get() - 1.0).abs() < 0.001);
     }
 
     #[derive(Debug, Default)]
@@ -228,11 +230,11 @@ mod tests {
 
     #[test]
     fn test_paramset() {
-        let holder = Rc::new(RefCell::new(MultiShape::default()));
-        mutate(holder.clone(), &mut ConstGen { 0: 0.25 });
+        let mut holder = MultiShape::default();
+        mutate(&mut holder, &mut ConstGen { 0: 0.25 });
 
         let expected = 2.5; // 10.0 * 0.25
-        let pos = &holder.borrow().pos;
+        let pos = &holder.pos;
         assert!((pos.x.get_scaled() - expected).abs() < 0.001);
         assert!((pos.y.get_scaled() - expected).abs() < 0.001);
         assert!((pos.z.get_scaled() - expected).abs() < 0.001);
@@ -10,9 +10,13 @@ use nalgebra::{Point3, Vector3};
 use nphysics3d::object::ColliderHandle;
 use std::collections::HashMap;
 use std::env;
+use std::time::{Duration, Instant};
 
 use shapes::body_tree::{serialise, tree, Population};
 use shapes::physics;
+use shapes::placement;
+use shapes::recording::{Recorder, Recording, Replay};
+use shapes::terrain::Terrain;
 
 fn new_node(window: &mut window::Window, object: &physics::ObjectShape) -> scene::SceneNode {
     match object {
@@ -35,19 +39,67 @@ fn new_node(window: &mut window::Window, object: &physics::ObjectShape) -> scene
             plane.reorient(&pos, &(pos + norm), &up);
             plane
         }
+        physics::ObjectShape::Blocks(blocks) => {
+            let mut group = window.add_group();
+            for (pos, half_extents) in blocks {
+                let mut cube = group.add_cube(
+                    (half_extents.x * 2.0) as f32,
+                    (half_extents.y * 2.0) as f32,
+                    (half_extents.z * 2.0) as f32,
+                );
+                cube.set_local_transformation(nalgebra::convert(*pos));
+            }
+            group
+        }
     }
 }
 
+fn terrain_presets() -> Vec<Terrain> {
+    vec![
+        Terrain::Flat,
+        Terrain::Incline { angle: 0.15 },
+        Terrain::Heightfield {
+            seed: 1,
+            cells: 40,
+            cell_size: 1.0,
+            max_height: 0.6,
+        },
+        Terrain::Stairs {
+            steps: 10,
+            step_height: 0.15,
+            step_depth: 1.0,
+            width: 60.0,
+        },
+        Terrain::Obstacles {
+            seed: 1,
+            count: 60,
+            max_size: 1.0,
+            spread: 20.0,
+        },
+    ]
+}
+
 struct Renderer {
     window: window::Window,
     world: physics::World,
     objects: HashMap<ColliderHandle, scene::SceneNode>,
     population: Population,
+    creatures: Vec<physics::CreatureHandle>,
+    terrain: usize,
+    underwater: bool,
+    /// Set while the live world is being recorded
+    recorder: Option<Recorder>,
+    /// Set when playing back a recording instead of simulating
+    replay: Option<Replay>,
+    replay_nodes: HashMap<usize, scene::SceneNode>,
+    paused: bool,
 }
 
 const SPACING: f64 = 10.0;
+const MAX_STEPS_PER_FRAME: usize = 10;
 const POP_SIZE: usize = 3;
 const TREE_DEPTH: usize = 3;
+const RECORDING_PATH: &str = "./recording.json";
 
 impl Renderer {
     fn new() -> Self {
@@ -56,13 +108,78 @@ impl Renderer {
             world: physics::World::default(),
             objects: HashMap::new(),
             population: Population::new(),
+            creatures: Vec::new(),
+            terrain: 0,
+            underwater: false,
+            recorder: None,
+            replay: None,
+            replay_nodes: HashMap::new(),
+            paused: false,
+        }
+    }
+
+    /// Replaces the live world with playback of the recording
+    fn load_replay(&mut self, path: &str) {
+        let recording = Recording::load(path).expect("Failed to load recording");
+        for mut node in self.objects.values_mut() {
+            self.window.remove(&mut node);
+        }
+        self.objects.clear();
+
+        for (id, obj) in recording.objects.iter().enumerate() {
+            let mut node = new_node(&mut self.window, &obj.shape);
+            node.set_color(obj.colour.r, obj.colour.g, obj.colour.b);
+            node.set_visible(false);
+            self.replay_nodes.insert(id, node);
+        }
+        self.replay = Some(Replay::new(recording));
+    }
+
+    /// Shows the objects alive in the replay's current frame, in their recorded positions
+    fn update_replay_nodes(&mut self) {
+        let replay = match self.replay {
+            Some(ref r) => r,
+            None => return,
+        };
+
+        for node in self.replay_nodes.values_mut() {
+            node.set_visible(false);
+        }
+        for (id, _, position) in replay.poses() {
+            if let Some(node) = self.replay_nodes.get_mut(&id) {
+                node.set_local_transformation(nalgebra::convert(position));
+                node.set_visible(true);
+            }
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        match self.recorder.take() {
+            Some(recorder) => match recorder.finish().save(RECORDING_PATH) {
+                Ok(_) => println!("saved recording to {}", RECORDING_PATH),
+                Err(e) => eprintln!("failed to save recording: {}", e),
+            },
+            None => {
+                let mut recorder = Recorder::new();
+                recorder.record(&self.world);
+                self.recorder = Some(recorder);
+            }
+        }
+    }
+
+    /// Moves playback by the given number of frames, pausing it
+    fn scrub(&mut self, frames: isize) {
+        if let Some(ref mut replay) = self.replay {
+            let frame = (replay.frame() as isize + frames).max(0) as usize;
+            replay.seek(frame);
+            self.paused = true;
         }
     }
 
     fn reset_population<P: Into<::std::path::PathBuf>>(&mut self, path: P) {
         const LOAD: bool = false;
 
-        let mut pop = if LOAD {
+        let pop = if LOAD {
             serialise::load(path)
         } else {
             (0..POP_SIZE)
@@ -81,81 +198,126 @@ impl Renderer {
         // add new population
         {
             let mut r = physics::PhysicalRealiser::new(&mut self.world);
+            r.ground_clearance = Some(physics::DEFAULT_GROUND_CLEARANCE);
             r.next_spawn_pos.x -= SPACING * (pop.len() as f64) / 2.0;
-            for (i, mut tree) in pop.iter_mut().enumerate() {
-                let i = i as shapes::body_tree::Coord;
-                r.next_spawn_pos.x += SPACING;
-                tree.realise(&mut r);
-            }
-        }
-
-        // add objects from physics world to renderer
-        for (handle, _collider, obj) in self.world.objects() {
-            let node = new_node(&mut self.window, &obj.shape);
-            self.objects.insert(handle, node);
+            self.creatures = pop
+                .iter()
+                .map(|tree| {
+                    r.next_spawn_pos.x += SPACING;
+                    r.realise(tree)
+                })
+                .collect();
         }
 
+        self.sync_nodes();
         self.population = pop;
     }
 
     fn mutate_population(&mut self) {
-        // mutate
-        for mut tree in self.population.iter_mut() {
+        // mutate and respawn each creature in place
+        for (tree, creature) in self.population.iter_mut().zip(self.creatures.iter_mut()) {
             tree.mutate(0.2, 0.05);
+            // trees that can't be repaired are left overlapping, and will likely blow up
+            let _ = placement::repair(tree);
+            *creature = self
+                .world
+                .replace_creature(*creature, tree)
+                .expect("Creature missing from world");
         }
 
-        // clear old population
-        {
-            self.world.clear();
-            for mut node in self.objects.values_mut() {
-                self.window.remove(&mut node);
-            }
-            self.objects.clear();
-        }
+        self.sync_nodes();
+    }
 
-        // add new population
-        {
-            let mut r = physics::PhysicalRealiser::new(&mut self.world);
-            for (i, mut tree) in self.population.iter_mut().enumerate() {
-                let i = i as shapes::body_tree::Coord;
-                r.next_spawn_pos = Vector3::new(i * SPACING, 5.0, 0.0);
-                tree.realise(&mut r);
+    /// Removes nodes for objects no longer in the world, and adds nodes for new objects
+    fn sync_nodes(&mut self) {
+        let live: Vec<ColliderHandle> = self.world.objects().map(|(h, _)| h).collect();
+        let window = &mut self.window;
+        self.objects.retain(|handle, node| {
+            let keep = live.contains(handle);
+            if !keep {
+                window.remove(node);
             }
-        }
+            keep
+        });
 
-        // add objects from physics world to renderer
-        for (handle, _collider, obj) in self.world.objects() {
-            let node = new_node(&mut self.window, &obj.shape);
-            self.objects.insert(handle, node);
+        for (handle, obj) in self.world.objects() {
+            if !self.objects.contains_key(&handle) {
+                let node = new_node(&mut self.window, &obj.shape);
+                self.objects.insert(handle, node);
+            }
         }
     }
 
+    /// Usage: `renderer [population.json]` or `renderer --replay <recording.json>`
     fn start(&mut self) {
-        let path = env::args()
-            .nth(1)
-            .unwrap_or_else(|| "./population.json".to_owned());
+        let args: Vec<String> = env::args().skip(1).collect();
+        let path = match args.get(0).map(|s| s.as_str()) {
+            Some("--replay") => {
+                let recording = args.get(1).expect("Missing recording path");
+                self.load_replay(recording);
+                "./population.json".to_owned()
+            }
+            Some(path) => path.to_owned(),
+            None => "./population.json".to_owned(),
+        };
 
-        self.reset_population(&path);
+        if self.replay.is_none() {
+            self.reset_population(&path);
+        }
 
         let mut camera =
             camera::ArcBall::new(Point3::new(0.0, 30.0, 50.0), Point3::new(0.0, 0.0, 0.0));
 
         self.window.set_light(light::Light::StickToCamera);
 
+        let timestep = match self.replay {
+            Some(ref r) => r.recording().timestep,
+            None => self.world.config().timestep,
+        };
+        let mut last_frame = Instant::now();
+        let mut unsimulated = 0.0;
+
         while self.window.render_with_camera(&mut camera) {
-            // step world
-            self.world.tick();
+            // step world in fixed steps to catch up with real time, giving up if too far behind
+            let now = Instant::now();
+            unsimulated += duration_secs(now - last_frame);
+            last_frame = now;
+
+            if self.paused {
+                unsimulated = 0.0;
+            }
+
+            let mut steps = 0;
+            while unsimulated >= timestep {
+                match self.replay {
+                    Some(ref mut replay) => {
+                        replay.step_forward();
+                    }
+                    None => {
+                        self.world.tick();
+                        if let Some(ref mut recorder) = self.recorder {
+                            recorder.record(&self.world);
+                        }
+                    }
+                }
+                unsimulated -= timestep;
+                steps += 1;
+                if steps >= MAX_STEPS_PER_FRAME {
+                    unsimulated = 0.0;
+                    break;
+                }
+            }
 
             // update scene
-            for (handle, obj, collider, body) in self.world.colliders() {
+            self.update_replay_nodes();
+            for (handle, obj, position, active) in self.world.colliders() {
                 let mut node = match self.objects.get_mut(&handle) {
                     Some(n) => n,
                     None => continue,
                 };
-                let active = body.is_active();
                 let color = obj.colour;
                 if active {
-                    node.set_local_transformation(nalgebra::convert(*collider.position()));
+                    node.set_local_transformation(nalgebra::convert(position));
                     node.set_color(color.r, color.g, color.b);
                 } else {
                     node.set_color(color.r * 0.25, color.g * 0.25, color.b * 0.25);
@@ -165,9 +327,41 @@ impl Renderer {
             // keyboard
             for mut e in self.window.events().iter() {
                 if let WindowEvent::Key(key, _, Action::Press, _) = e.value {
+                    if self.replay.is_some() {
+                        match key {
+                            Key::Space => self.paused = !self.paused,
+                            Key::Left => self.scrub(-1),
+                            Key::Right => self.scrub(1),
+                            Key::PageUp => self.scrub(-60),
+                            Key::PageDown => self.scrub(60),
+                            Key::Home => {
+                                if let Some(ref mut replay) = self.replay {
+                                    replay.seek(0);
+                                }
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
                     match key {
                         Key::Enter => self.reset_population(&path),
+                        Key::R => self.toggle_recording(),
                         Key::Space => self.mutate_population(),
+                        Key::T => {
+                            let presets = terrain_presets();
+                            self.terrain = (self.terrain + 1) % presets.len();
+                            self.world.set_terrain(presets[self.terrain].clone());
+                            self.reset_population(&path);
+                        }
+                        Key::W => {
+                            self.underwater = !self.underwater;
+                            self.world.set_environment(if self.underwater {
+                                physics::Environment::Water { drag: 2.0 }
+                            } else {
+                                physics::Environment::Land
+                            });
+                        }
                         _ => {}
                     }
                 }
@@ -176,6 +370,10 @@ impl Renderer {
     }
 }
 
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9
+}
+
 fn main() {
     Renderer::new().start();
 }
@@ -0,0 +1,196 @@
+//! The operations `physics::World` needs from a physics engine, so that engines can be swapped
+//! without touching the realiser.
+//!
+//! Everything a backend simulates is built from cuboids, joined into trees of links hanging
+//! off a static ground body.
+
+use nalgebra::{Isometry3, Vector3};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use body_tree::Coord;
+use physics::WorldConfig;
+
+/// Highest collision group id, as ncollide supports no more than 30
+pub const MAX_GROUP: usize = 29;
+
+const ALL_GROUPS: u32 = (1 << (MAX_GROUP + 1)) - 1;
+
+/// How a link is joined to its parent
+#[derive(Debug, Clone, Copy)]
+pub enum LinkJoint {
+    /// Not joined at all, starting at the given position. Only used with the ground as parent
+    Free(Isometry3<Coord>),
+    /// Rigidly attached at the given position relative to the parent
+    Fixed(Isometry3<Coord>),
+    /// Hinged about the x axis at the given offset from the parent, driven by a motor
+    Revolute {
+        offset: Vector3<Coord>,
+        motor_speed: Coord,
+        /// Most impulse the motor may apply in a single step, as nphysics treats it
+        max_torque: Coord,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum ColliderShape {
+    /// Half extents
+    Cuboid(Vector3<Coord>),
+    /// Cuboids with their half extents, relative to the collider
+    Cuboids(Vec<(Isometry3<Coord>, Vector3<Coord>)>),
+}
+
+/// Which colliders can touch each other, as bitmasks of group ids. Two colliders interact if
+/// each is a member of a group in the other's whitelist, and neither is a member of a group in
+/// the other's blacklist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionFilter {
+    pub membership: u32,
+    pub whitelist: u32,
+    pub blacklist: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Velocity {
+    pub linear: Vector3<Coord>,
+    pub angular: Vector3<Coord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Contact<C> {
+    Started(C, C),
+    Stopped(C, C),
+}
+
+pub trait PhysicsBackend {
+    type Body: Copy + Eq + Hash + Debug;
+    type Collider: Copy + Eq + Hash + Debug;
+
+    fn new(config: &WorldConfig) -> Self;
+
+    fn set_gravity(&mut self, gravity: Vector3<Coord>);
+
+    fn step(&mut self);
+
+    /// Removes every body and collider
+    fn clear(&mut self);
+
+    /// The static body that the ground and the roots of creatures hang off
+    fn ground(&self) -> Self::Body;
+
+    /// Adds a cuboid link with unit density joined to `parent`
+    fn add_link(
+        &mut self,
+        parent: Self::Body,
+        joint: &LinkJoint,
+        half_extents: &Vector3<Coord>,
+    ) -> Self::Body;
+
+    /// Removes the body with its colliders, and any links joined to it
+    fn remove_body(&mut self, body: Self::Body);
+
+    fn add_collider(
+        &mut self,
+        body: Self::Body,
+        shape: &ColliderShape,
+        position: &Isometry3<Coord>,
+        margin: Coord,
+        filter: &CollisionFilter,
+    ) -> Self::Collider;
+
+    /// None if the collider has been removed
+    fn collider_position(&self, collider: Self::Collider) -> Option<Isometry3<Coord>>;
+
+    fn collider_body(&self, collider: Self::Collider) -> Option<Self::Body>;
+
+    fn body_position(&self, body: Self::Body) -> Isometry3<Coord>;
+
+    fn body_velocity(&self, body: Self::Body) -> Velocity;
+
+    /// False if the body is asleep
+    fn is_active(&self, body: Self::Body) -> bool;
+
+    /// Applies a force and torque about the centre of mass during the next step only
+    fn apply_force(&mut self, body: Self::Body, force: Vector3<Coord>, torque: Vector3<Coord>);
+
+    /// Angle of a revolute joint, None for any other link
+    fn joint_angle(&self, body: Self::Body) -> Option<Coord>;
+
+    /// Impulse the motor of a revolute joint applied about its axis during the last step. None
+    /// for any other link, or if the backend can't tell
+    fn motor_impulse(&self, body: Self::Body) -> Option<Coord>;
+
+    /// Switches off the motor of a revolute joint, leaving it free to swing
+    fn disable_motor(&mut self, body: Self::Body);
+
+    /// Contacts that started or stopped during the last step
+    fn contact_events(&self) -> Vec<Contact<Self::Collider>>;
+}
+
+fn mask(groups: &[usize]) -> u32 {
+    groups.iter().fold(0, |mask, group| {
+        assert!(*group <= MAX_GROUP, "bad collision group {}", group);
+        mask | (1 << group)
+    })
+}
+
+impl CollisionFilter {
+    /// Member of the given groups, interacting with everything
+    pub fn new(membership: &[usize]) -> Self {
+        Self {
+            membership: mask(membership),
+            whitelist: ALL_GROUPS,
+            blacklist: 0,
+        }
+    }
+
+    pub fn with_whitelist(self, groups: &[usize]) -> Self {
+        Self {
+            whitelist: mask(groups),
+            ..self
+        }
+    }
+
+    pub fn with_blacklist(self, groups: &[usize]) -> Self {
+        Self {
+            blacklist: mask(groups),
+            ..self
+        }
+    }
+
+    pub fn can_interact_with(&self, other: &CollisionFilter) -> bool {
+        self.membership & other.whitelist != 0
+            && other.membership & self.whitelist != 0
+            && self.membership & other.blacklist == 0
+            && other.membership & self.blacklist == 0
+    }
+
+    /// Group ids set in the mask
+    pub fn groups(mask: u32) -> Vec<usize> {
+        (0..=MAX_GROUP).filter(|g| mask & (1 << g) != 0).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters() {
+        let ground = CollisionFilter::new(&[0]);
+        let a = CollisionFilter::new(&[1])
+            .with_whitelist(&[0, 1, 2])
+            .with_blacklist(&[2]);
+        let b = CollisionFilter::new(&[2]).with_whitelist(&[0, 1, 2]);
+        let other = CollisionFilter::new(&[5]).with_whitelist(&[0, 5]);
+
+        assert!(a.can_interact_with(&ground));
+        assert!(a.can_interact_with(&a));
+        assert!(!a.can_interact_with(&b));
+        assert!(!b.can_interact_with(&a));
+        assert!(!a.can_interact_with(&other));
+        assert!(other.can_interact_with(&ground));
+
+        assert_eq!(CollisionFilter::groups(a.whitelist), vec![0, 1, 2]);
+    }
+}
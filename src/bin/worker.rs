@@ -0,0 +1,58 @@
+//! Evaluation worker process, serving coordinators over TCP or a unix socket.
+//!
+//! Usage: `worker tcp [address]` or `worker unix <path>`
+
+extern crate shapes;
+
+use shapes::remote;
+use std::env;
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::process;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+fn usage() -> ! {
+    eprintln!("usage: worker tcp [address] | worker unix <path>");
+    process::exit(1)
+}
+
+/// Announced on stdout so that whoever started us can find us
+fn announce<A: ::std::fmt::Debug>(addr: A) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "listening on {:?}", addr)?;
+    stdout.flush()
+}
+
+fn run() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.get(0).map(|s| s.as_str()) {
+        Some("tcp") => {
+            let addr = args.get(1).map_or("127.0.0.1:0", |s| s.as_str());
+            let listener = TcpListener::bind(addr)?;
+            announce(listener.local_addr()?)?;
+            remote::serve(listener.incoming());
+            Ok(())
+        }
+
+        #[cfg(unix)]
+        Some("unix") => {
+            let path = args.get(1).unwrap_or_else(|| usage());
+            let listener = UnixListener::bind(path)?;
+            announce(path)?;
+            remote::serve(listener.incoming());
+            Ok(())
+        }
+
+        _ => usage(),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("worker failed: {}", e);
+        process::exit(1);
+    }
+}
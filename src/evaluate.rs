@@ -0,0 +1,250 @@
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use backend::PhysicsBackend;
+use body_tree::tree::BodyTree;
+use body_tree::{Coord, Population};
+use fitness::{Fitness, HorizontalDistance};
+use nphysics_backend::NPhysicsBackend;
+use physics::{self, CreatureHandle, PhysicalRealiser, World};
+use placement;
+use recording::{Recorder, Recording};
+
+pub type Score = f64;
+
+/// Given by `evaluate` to creatures whose simulation blew up, however far they were flung
+pub const INVALID_SCORE: Score = 0.0;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EvaluationSettings {
+    /// Number of physics steps to simulate
+    pub ticks: usize,
+    /// Steps to simulate before the score starts being measured, so the creature can settle
+    /// onto the ground
+    pub settle_ticks: usize,
+    /// Gap between the creature's lowest point and the ground when spawned
+    pub ground_clearance: Coord,
+}
+
+impl Default for EvaluationSettings {
+    fn default() -> Self {
+        Self {
+            ticks: 600,
+            settle_ticks: 120,
+            ground_clearance: physics::DEFAULT_GROUND_CLEARANCE,
+        }
+    }
+}
+
+/// Realises the tree alone in a fresh world, and scores it by the horizontal distance its
+/// centre of mass moves over the evaluation, as `fitness::HorizontalDistance`. Overlapping
+/// parts are repaired first, and creatures that can't be repaired or fail the world's sanity
+/// checks score `INVALID_SCORE`.
+pub fn evaluate(tree: &BodyTree, settings: &EvaluationSettings) -> Score {
+    evaluate_with::<NPhysicsBackend>(tree, settings)
+}
+
+/// Same as `evaluate`, but simulated with the given physics backend
+pub fn evaluate_with<B: PhysicsBackend>(tree: &BodyTree, settings: &EvaluationSettings) -> Score {
+    evaluate_fitness::<B, _>(tree, settings, HorizontalDistance::default()).unwrap_or(INVALID_SCORE)
+}
+
+/// Same as `evaluate`, but scored by the given fitness, which should be fresh. None for invalid
+/// creatures, as no score is safely worse than every valid one for all fitnesses.
+pub fn evaluate_fitness<B: PhysicsBackend, F: Fitness<B> + 'static>(
+    tree: &BodyTree,
+    settings: &EvaluationSettings,
+    fitness: F,
+) -> Option<Score> {
+    let fitness: Box<Fitness<B>> = Box::new(fitness);
+    evaluate_objectives(tree, settings, vec![fitness]).map(|scores| scores[0])
+}
+
+/// Same as `evaluate_fitness`, but scored by each of the given objectives, which should be
+/// fresh
+pub fn evaluate_objectives<B: PhysicsBackend>(
+    tree: &BodyTree,
+    settings: &EvaluationSettings,
+    mut objectives: Vec<Box<Fitness<B>>>,
+) -> Option<Vec<Score>> {
+    run(tree, settings, &mut objectives, &mut |_| {})
+}
+
+/// Same as `evaluate_objectives`, but also calls `observe` once after realisation and again
+/// after every tick, until the creature becomes invalid.
+pub fn evaluate_observed<B: PhysicsBackend>(
+    tree: &BodyTree,
+    settings: &EvaluationSettings,
+    mut objectives: Vec<Box<Fitness<B>>>,
+    observe: &mut FnMut(&World<B>),
+) -> Option<Vec<Score>> {
+    run(tree, settings, &mut objectives, observe)
+}
+
+/// Same as `evaluate`, also recording every tick so the run can be replayed. The recording
+/// stops early if the creature becomes invalid, and is empty if it can't be repaired.
+pub fn evaluate_recorded(tree: &BodyTree, settings: &EvaluationSettings) -> (Score, Recording) {
+    let mut recorder = Recorder::<NPhysicsBackend>::new();
+    let objectives: Vec<Box<Fitness>> = vec![Box::new(HorizontalDistance::default())];
+    let score = evaluate_observed(tree, settings, objectives, &mut |world| {
+        recorder.record(world)
+    });
+    (
+        score.map_or(INVALID_SCORE, |scores| scores[0]),
+        recorder.finish(),
+    )
+}
+
+/// Scores of each objective, None if the creature is invalid. Calls `observe` once after
+/// realisation and again after every tick.
+fn run<B: PhysicsBackend>(
+    tree: &BodyTree,
+    settings: &EvaluationSettings,
+    objectives: &mut [Box<Fitness<B>>],
+    observe: &mut FnMut(&World<B>),
+) -> Option<Vec<Score>> {
+    let mut tree = tree.clone();
+    placement::repair(&mut tree).ok()?;
+
+    let mut world = World::<B>::default();
+    world.clear(); // adds ground
+    let creature = {
+        let mut r = PhysicalRealiser::new(&mut world);
+        r.ground_clearance = Some(settings.ground_clearance);
+        r.realise(&tree)
+    };
+    observe(&world);
+
+    if !simulate(&mut world, creature, settings.settle_ticks, observe) {
+        return None;
+    }
+
+    {
+        let c = world.creature(creature).expect("Creature disappeared");
+        for objective in objectives.iter_mut() {
+            objective.start(&c);
+        }
+    }
+    {
+        let measure = &mut |world: &World<B>| {
+            observe(world);
+            if let Some(c) = world.creature(creature) {
+                for objective in objectives.iter_mut() {
+                    objective.tick(&c);
+                }
+            }
+        };
+        if !simulate(&mut world, creature, settings.ticks, measure) {
+            return None;
+        }
+    }
+
+    let c = world.creature(creature).expect("Creature disappeared");
+    Some(objectives.iter().map(|o| o.score(&c)).collect())
+}
+
+/// Returns false as soon as the creature becomes invalid
+fn simulate<B: PhysicsBackend>(
+    world: &mut World<B>,
+    creature: CreatureHandle,
+    ticks: usize,
+    observe: &mut FnMut(&World<B>),
+) -> bool {
+    for _ in 0..ticks {
+        world.tick();
+        observe(world);
+        let valid = world
+            .creature(creature)
+            .map_or(false, |c| c.invalid().is_none());
+        if !valid {
+            return false;
+        }
+    }
+    true
+}
+
+/// Scores in population order.
+pub fn evaluate_population(population: &Population, settings: &EvaluationSettings) -> Vec<Score> {
+    population
+        .iter()
+        .map(|tree| evaluate(tree, settings))
+        .collect()
+}
+
+/// Scores of each objective in population order, from fresh objectives for every tree. None for
+/// invalid creatures.
+pub fn evaluate_population_objectives<F>(
+    population: &Population,
+    settings: &EvaluationSettings,
+    objectives: F,
+) -> Vec<Option<Vec<Score>>>
+where
+    F: Fn() -> Vec<Box<Fitness>>,
+{
+    population
+        .iter()
+        .map(|tree| evaluate_objectives(tree, settings, objectives()))
+        .collect()
+}
+
+/// Same as `evaluate_population`, but spread across `threads` workers that each simulate in
+/// their own world. Results are identical regardless of the thread count.
+pub fn evaluate_population_parallel(
+    population: &Population,
+    settings: &EvaluationSettings,
+    threads: usize,
+) -> Vec<Score> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("Failed to create thread pool");
+
+    pool.install(|| {
+        population
+            .par_iter()
+            .map(|tree| evaluate(tree, settings))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body_tree::tree;
+
+    #[test]
+    fn parallel_is_deterministic() {
+        let pop: Population = (0..6).map(|_| tree::grow_random_tree(2)).collect();
+        let settings = EvaluationSettings {
+            ticks: 60,
+            ..EvaluationSettings::default()
+        };
+
+        let serial = evaluate_population(&pop, &settings);
+        assert_eq!(serial.len(), pop.len());
+        for threads in 1..4 {
+            assert_eq!(
+                serial,
+                evaluate_population_parallel(&pop, &settings, threads)
+            );
+        }
+    }
+
+    #[test]
+    fn recorded_matches_unrecorded() {
+        let tree = tree::grow_random_tree(2);
+        let settings = EvaluationSettings {
+            ticks: 60,
+            ..EvaluationSettings::default()
+        };
+
+        let (score, recording) = evaluate_recorded(&tree, &settings);
+        assert_eq!(score, evaluate(&tree, &settings));
+        if score != INVALID_SCORE {
+            assert_eq!(
+                recording.frames.len(),
+                1 + settings.settle_ticks + settings.ticks
+            );
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Objectives that score a creature from its state over an evaluation, combined with weights
+//! into whatever an experiment is after.
+//!
+//! Higher scores are always better, so penalties score negatively.
+
+use nalgebra::{Point3, Unit, Vector3};
+
+use backend::PhysicsBackend;
+use body_tree::Coord;
+use evaluate::Score;
+use nphysics_backend::NPhysicsBackend;
+use physics::Creature;
+
+/// Measures a single creature over one evaluation, so a fresh instance is needed for each
+pub trait Fitness<B: PhysicsBackend = NPhysicsBackend> {
+    /// Called once the creature has settled, when measuring starts
+    fn start(&mut self, _creature: &Creature<B>) {}
+
+    /// Called after every measured tick
+    fn tick(&mut self, _creature: &Creature<B>) {}
+
+    /// Called once measuring is over
+    fn score(&self, creature: &Creature<B>) -> Score;
+}
+
+/// Horizontal distance the centre of mass moves, in any direction
+#[derive(Debug, Clone)]
+pub struct HorizontalDistance {
+    start: Point3<Coord>,
+}
+
+/// Distance the centre of mass moves along an axis, negative if it goes backwards
+#[derive(Debug, Clone)]
+pub struct AxisDistance {
+    axis: Unit<Vector3<Coord>>,
+    start: Point3<Coord>,
+}
+
+/// Greatest height reached by the highest point of any part
+#[derive(Debug, Default, Clone)]
+pub struct MaxHeight {
+    max: Coord,
+}
+
+/// Greatest height the root reaches above where it started
+#[derive(Debug, Default, Clone)]
+pub struct Jump {
+    start: Coord,
+    peak: Coord,
+}
+
+/// How much closer the centre of mass gets to a target
+#[derive(Debug, Clone)]
+pub struct LightFollowing {
+    target: Point3<Coord>,
+    start_distance: Coord,
+}
+
+/// Mean alignment of the root's local up axis with world up, from -1 upside down to 1
+#[derive(Debug, Clone)]
+pub struct Upright {
+    up: Unit<Vector3<Coord>>,
+    total: Coord,
+    ticks: usize,
+}
+
+/// Negative total mass
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BodySizePenalty;
+
+/// Negative number of parts
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PartCountPenalty;
+
+/// Weighted sum of other objectives
+pub struct Weighted<B: PhysicsBackend = NPhysicsBackend> {
+    objectives: Vec<(Score, Box<Fitness<B>>)>,
+}
+
+impl Default for HorizontalDistance {
+    fn default() -> Self {
+        Self {
+            start: Point3::origin(),
+        }
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for HorizontalDistance {
+    fn start(&mut self, creature: &Creature<B>) {
+        self.start = creature.centre_of_mass();
+    }
+
+    fn score(&self, creature: &Creature<B>) -> Score {
+        let diff = creature.centre_of_mass() - self.start;
+        (diff.x * diff.x + diff.z * diff.z).sqrt()
+    }
+}
+
+impl AxisDistance {
+    pub fn new(axis: Vector3<Coord>) -> Self {
+        Self {
+            axis: Unit::new_normalize(axis),
+            start: Point3::origin(),
+        }
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for AxisDistance {
+    fn start(&mut self, creature: &Creature<B>) {
+        self.start = creature.centre_of_mass();
+    }
+
+    fn score(&self, creature: &Creature<B>) -> Score {
+        (creature.centre_of_mass() - self.start).dot(self.axis.as_ref())
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for MaxHeight {
+    fn start(&mut self, creature: &Creature<B>) {
+        self.max = creature.height();
+    }
+
+    fn tick(&mut self, creature: &Creature<B>) {
+        self.max = self.max.max(creature.height());
+    }
+
+    fn score(&self, _creature: &Creature<B>) -> Score {
+        self.max
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for Jump {
+    fn start(&mut self, creature: &Creature<B>) {
+        self.start = creature
+            .root_position()
+            .map_or(0.0, |pos| pos.translation.vector.y);
+        self.peak = self.start;
+    }
+
+    fn tick(&mut self, creature: &Creature<B>) {
+        if let Some(pos) = creature.root_position() {
+            self.peak = self.peak.max(pos.translation.vector.y);
+        }
+    }
+
+    fn score(&self, _creature: &Creature<B>) -> Score {
+        self.peak - self.start
+    }
+}
+
+impl LightFollowing {
+    pub fn new(target: Point3<Coord>) -> Self {
+        Self {
+            target,
+            start_distance: 0.0,
+        }
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for LightFollowing {
+    fn start(&mut self, creature: &Creature<B>) {
+        self.start_distance = (self.target - creature.centre_of_mass()).norm();
+    }
+
+    fn score(&self, creature: &Creature<B>) -> Score {
+        self.start_distance - (self.target - creature.centre_of_mass()).norm()
+    }
+}
+
+impl Upright {
+    /// `up` is in the root part's local frame
+    pub fn new(up: Vector3<Coord>) -> Self {
+        Self {
+            up: Unit::new_normalize(up),
+            total: 0.0,
+            ticks: 0,
+        }
+    }
+}
+
+impl Default for Upright {
+    fn default() -> Self {
+        Self::new(Vector3::y())
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for Upright {
+    fn tick(&mut self, creature: &Creature<B>) {
+        if let Some(pos) = creature.root_position() {
+            self.total += (pos.rotation * self.up.as_ref()).y;
+            self.ticks += 1;
+        }
+    }
+
+    fn score(&self, _creature: &Creature<B>) -> Score {
+        if self.ticks == 0 {
+            0.0
+        } else {
+            self.total / self.ticks as Coord
+        }
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for BodySizePenalty {
+    fn score(&self, creature: &Creature<B>) -> Score {
+        -creature.mass()
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for PartCountPenalty {
+    fn score(&self, creature: &Creature<B>) -> Score {
+        -(creature.part_count() as Score)
+    }
+}
+
+impl<B: PhysicsBackend> Default for Weighted<B> {
+    fn default() -> Self {
+        Self {
+            objectives: Vec::new(),
+        }
+    }
+}
+
+impl<B: PhysicsBackend> Weighted<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<F: Fitness<B> + 'static>(mut self, weight: Score, objective: F) -> Self {
+        self.objectives.push((weight, Box::new(objective)));
+        self
+    }
+}
+
+impl<B: PhysicsBackend> Fitness<B> for Weighted<B> {
+    fn start(&mut self, creature: &Creature<B>) {
+        for (_, objective) in &mut self.objectives {
+            objective.start(creature);
+        }
+    }
+
+    fn tick(&mut self, creature: &Creature<B>) {
+        for (_, objective) in &mut self.objectives {
+            objective.tick(creature);
+        }
+    }
+
+    fn score(&self, creature: &Creature<B>) -> Score {
+        self.objectives
+            .iter()
+            .map(|(weight, objective)| weight * objective.score(creature))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body_tree::body::def::new_cuboid;
+    use body_tree::tree::BodyTree;
+    use physics::{CreatureHandle, PhysicalRealiser, World, WorldConfig};
+
+    /// A world with the given gravity and an unrotated cube dropped into it from high up
+    fn cube(gravity: Coord, size: Coord) -> (World, CreatureHandle) {
+        let mut world: World = World::new(WorldConfig {
+            gravity: Vector3::new(0.0, gravity, 0.0),
+            ..WorldConfig::default()
+        });
+        world.clear();
+        let handle = {
+            let mut r = PhysicalRealiser::new(&mut world);
+            r.realise(&BodyTree::with_root(new_cuboid(
+                (size, size, size),
+                (0.0, 0.0, 0.0),
+                (0.0, 0.0, 0.0),
+            )))
+        };
+        (world, handle)
+    }
+
+    /// Score after measuring for half a second, too short for anything to land
+    fn measure<F: Fitness>(world: &mut World, handle: CreatureHandle, fitness: &mut F) -> Score {
+        fitness.start(&world.creature(handle).unwrap());
+        for _ in 0..30 {
+            world.tick();
+            fitness.tick(&world.creature(handle).unwrap());
+        }
+        fitness.score(&world.creature(handle).unwrap())
+    }
+
+    #[test]
+    fn weighted() {
+        let (mut world, handle) = cube(-9.81, 1.0);
+        let mut fitness = Weighted::new()
+            .with(1.0, Upright::default())
+            .with(0.5, PartCountPenalty)
+            .with(2.0, AxisDistance::new(Vector3::x()));
+
+        // an unrotated cube sitting still is upright, and goes nowhere
+        let score = measure(&mut world, handle, &mut fitness);
+        assert!((score - (1.0 - 0.5)).abs() < 0.01, "score {}", score);
+    }
+
+    #[test]
+    fn jump() {
+        let (mut world, handle) = cube(-9.81, 1.0);
+        assert_eq!(measure(&mut world, handle, &mut Jump::default()), 0.0);
+
+        // falling upwards for half a second covers about g * t^2 / 2
+        let (mut world, handle) = cube(9.81, 1.0);
+        let score = measure(&mut world, handle, &mut Jump::default());
+        assert!(1.0 < score && score < 1.5, "score {}", score);
+    }
+
+    #[test]
+    fn max_height() {
+        let (mut world, handle) = cube(-9.81, 1.0);
+        let start = world.creature(handle).unwrap().height();
+        let score = measure(&mut world, handle, &mut MaxHeight::default());
+        assert_eq!(score, start);
+        assert!(world.creature(handle).unwrap().height() < start - 1.0);
+
+        let (mut world, handle) = cube(9.81, 1.0);
+        let score = measure(&mut world, handle, &mut MaxHeight::default());
+        assert_eq!(score, world.creature(handle).unwrap().height());
+        assert!(score > start + 1.0);
+    }
+
+    #[test]
+    fn light_following() {
+        let (mut world, handle) = cube(-9.81, 1.0);
+        let start = world.creature(handle).unwrap().centre_of_mass();
+        let mut below = LightFollowing::new(Point3::origin());
+        let score = measure(&mut world, handle, &mut below);
+        let fallen = start.y - world.creature(handle).unwrap().centre_of_mass().y;
+        assert!((score - fallen).abs() < 1e-6, "{} vs {}", score, fallen);
+
+        let (mut world, handle) = cube(-9.81, 1.0);
+        let mut above = LightFollowing::new(Point3::new(0.0, 100.0, 0.0));
+        assert!(measure(&mut world, handle, &mut above) < -1.0);
+    }
+
+    #[test]
+    fn upright() {
+        let score = |up| {
+            let (mut world, handle) = cube(-9.81, 1.0);
+            measure(&mut world, handle, &mut Upright::new(up))
+        };
+        assert!((score(Vector3::y()) - 1.0).abs() < 0.01);
+        assert!((score(-Vector3::y()) + 1.0).abs() < 0.01);
+        assert!(score(Vector3::x()).abs() < 0.01);
+
+        // nothing measured yet
+        let (world, handle) = cube(-9.81, 1.0);
+        assert_eq!(
+            Upright::default().score(&world.creature(handle).unwrap()),
+            0.0
+        );
+    }
+
+    #[test]
+    fn body_size_penalty() {
+        let (mut small, a) = cube(-9.81, 0.5);
+        let (mut large, b) = cube(-9.81, 1.0);
+        let small_score = measure(&mut small, a, &mut BodySizePenalty);
+        let large_score = measure(&mut large, b, &mut BodySizePenalty);
+        assert_eq!(small_score, -small.creature(a).unwrap().mass());
+        assert!(large_score < small_score && small_score < 0.0);
+    }
+}
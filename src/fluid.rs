@@ -0,0 +1,82 @@
+//! Viscous drag on body parts, for swimming creatures.
+
+use nalgebra::{Isometry3, Vector3};
+
+use body_tree::Coord;
+
+/// Every face moving into the fluid is pushed back along its normal, proportional to its area
+/// and normal velocity. Each face is sampled at the centre of its quarters, so that spinning
+/// is resisted too. Returns (force, torque about the centre).
+pub fn drag_on_cuboid(
+    coefficient: Coord,
+    pos: &Isometry3<Coord>,
+    half_extents: &Vector3<Coord>,
+    linear: &Vector3<Coord>,
+    angular: &Vector3<Coord>,
+) -> (Vector3<Coord>, Vector3<Coord>) {
+    const QUARTERS: [(Coord, Coord); 4] = [(-0.5, -0.5), (-0.5, 0.5), (0.5, -0.5), (0.5, 0.5)];
+    let mut force = Vector3::zeros();
+    let mut torque = Vector3::zeros();
+
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let quarter_area = half_extents[u] * half_extents[v];
+        for sign in &[-1.0, 1.0] {
+            for (qu, qv) in &QUARTERS {
+                let mut local_offset = Vector3::zeros();
+                local_offset[axis] = sign * half_extents[axis];
+                local_offset[u] = qu * half_extents[u];
+                local_offset[v] = qv * half_extents[v];
+
+                let mut normal = Vector3::zeros();
+                normal[axis] = *sign;
+
+                // from the centre to the sample point
+                let offset = pos.rotation * local_offset;
+                let normal = pos.rotation * normal;
+
+                let normal_vel = (linear + angular.cross(&offset)).dot(&normal);
+                if normal_vel > 0.0 {
+                    let sample_force = normal * (-coefficient * quarter_area * normal_vel);
+                    force += sample_force;
+                    torque += offset.cross(&sample_force);
+                }
+            }
+        }
+    }
+
+    (force, torque)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opposes_movement() {
+        let half = Vector3::new(1.0, 0.5, 0.25);
+        let (force, torque) = drag_on_cuboid(
+            2.0,
+            &Isometry3::identity(),
+            &half,
+            &Vector3::new(3.0, 0.0, 0.0),
+            &Vector3::zeros(),
+        );
+
+        // only the +x face, with area 1.0 * 0.5
+        assert!((force - Vector3::new(-3.0, 0.0, 0.0)).norm() < 0.0001);
+        assert!(torque.norm() < 0.0001);
+    }
+
+    #[test]
+    fn opposes_spinning() {
+        let half = Vector3::new(1.0, 1.0, 1.0);
+        let spin = Vector3::new(0.0, 2.0, 0.0);
+        let (force, torque) =
+            drag_on_cuboid(1.0, &Isometry3::identity(), &half, &Vector3::zeros(), &spin);
+
+        // faces are symmetrical so there's no net push, only resistance to the spin
+        assert!(force.norm() < 0.0001);
+        assert!(torque.dot(&spin) < 0.0);
+    }
+}
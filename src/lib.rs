@@ -3,7 +3,31 @@
 extern crate nalgebra;
 extern crate ncollide3d;
 extern crate nphysics3d;
+#[cfg(feature = "rapier")]
+extern crate rapier3d_f64;
 extern crate rand;
+extern crate rayon;
+extern crate serde;
+extern crate serde_json;
+
+#[macro_use]
+extern crate serde_derive;
 
 pub extern crate body_tree;
+pub mod backend;
+pub mod evaluate;
+pub mod fitness;
+pub mod fluid;
+pub mod mapelites;
+pub mod novelty;
+pub mod nphysics_backend;
+pub mod nsga2;
 pub mod physics;
+pub mod placement;
+#[cfg(feature = "rapier")]
+pub mod rapier_backend;
+pub mod recording;
+pub mod remote;
+pub mod sanity;
+pub mod speciation;
+pub mod terrain;
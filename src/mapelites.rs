@@ -0,0 +1,361 @@
+//! MAP-Elites, a quality-diversity archive keeping the best individual found for every cell of
+//! a grid over chosen features, so that good creatures of every shape and gait are kept rather
+//! than only the best overall.
+
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use body_tree::tree::BodyTree;
+use body_tree::{serialise, wrap, Coord, Population};
+use evaluate::{self, EvaluationSettings, Score};
+use fitness::Fitness;
+use physics::World;
+
+/// Something measured of an individual that it's placed in the grid by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Feature {
+    PartCount,
+    TreeDepth,
+    /// Height of the creature's bounding box when spawned
+    BodyHeight,
+    /// Mean absolute angular speed of its rotational joints over the evaluation
+    MeanJointSpeed,
+}
+
+/// One dimension of the grid, with values outside the range clamped into the end bins
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Axis {
+    pub feature: Feature,
+    pub min: Coord,
+    pub max: Coord,
+    pub bins: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Elite {
+    pub tree: BodyTree,
+    pub score: Score,
+    /// Value of each axis' feature
+    pub features: Vec<Coord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    axes: Vec<Axis>,
+    /// Row major, with the last axis varying fastest
+    cells: Vec<Option<Elite>>,
+}
+
+impl Feature {
+    fn name(&self) -> &'static str {
+        match self {
+            Feature::PartCount => "part_count",
+            Feature::TreeDepth => "tree_depth",
+            Feature::BodyHeight => "body_height",
+            Feature::MeanJointSpeed => "mean_joint_speed",
+        }
+    }
+}
+
+impl Axis {
+    pub fn new(feature: Feature, min: Coord, max: Coord, bins: usize) -> Self {
+        let axis = Self {
+            feature,
+            min,
+            max,
+            bins,
+        };
+        axis.check();
+        axis
+    }
+
+    fn check(&self) {
+        assert!(self.bins > 0 && self.max > self.min, "bad axis {:?}", self);
+    }
+
+    fn bin(&self, value: Coord) -> usize {
+        let t = (value - self.min) / (self.max - self.min);
+        ((t * self.bins as Coord).max(0.0) as usize).min(self.bins - 1)
+    }
+
+    /// Lowest value that falls into the bin
+    fn bin_start(&self, bin: usize) -> Coord {
+        self.min + (self.max - self.min) * bin as Coord / self.bins as Coord
+    }
+}
+
+/// Score from the given fitness and the value of each axis' feature, None if the creature is
+/// invalid
+pub fn evaluate_features<F: Fitness + 'static>(
+    tree: &BodyTree,
+    settings: &EvaluationSettings,
+    axes: &[Axis],
+    fitness: F,
+) -> Option<(Score, Vec<Coord>)> {
+    let mut body_height = None;
+    let mut last_angles: Option<Vec<(usize, Coord)>> = None;
+    let (mut joint_speed_total, mut joint_speed_samples) = (0.0, 0usize);
+
+    let objectives: Vec<Box<Fitness>> = vec![Box::new(fitness)];
+    let scores = evaluate::evaluate_observed(tree, settings, objectives, &mut |world: &World| {
+        let creature = match world.creatures().next().and_then(|c| world.creature(c)) {
+            Some(c) => c,
+            None => return,
+        };
+
+        if body_height.is_none() {
+            body_height = creature.aabb().map(|aabb| aabb.maxs().y - aabb.mins().y);
+        }
+
+        let angles = creature.joint_angles();
+        if let Some(last) = last_angles.take() {
+            let dt = world.config().timestep;
+            for ((_, before), (_, after)) in last.iter().zip(&angles) {
+                joint_speed_total += wrap(after - before, -PI, PI).abs() / dt;
+                joint_speed_samples += 1;
+            }
+        }
+        last_angles = Some(angles);
+    })?;
+
+    let features = axes
+        .iter()
+        .map(|axis| match axis.feature {
+            Feature::PartCount => tree.node_count() as Coord,
+            Feature::TreeDepth => tree.depth() as Coord,
+            Feature::BodyHeight => body_height.unwrap_or(0.0),
+            Feature::MeanJointSpeed => {
+                if joint_speed_samples == 0 {
+                    0.0
+                } else {
+                    joint_speed_total / joint_speed_samples as Coord
+                }
+            }
+        })
+        .collect();
+    Some((scores[0], features))
+}
+
+impl Archive {
+    pub fn new(axes: Vec<Axis>) -> Self {
+        let size = axes.iter().map(|a| a.bins).product();
+        Self {
+            axes,
+            cells: vec![None; size],
+        }
+    }
+
+    /// Panics if the saved axes or cells are inconsistent
+    pub fn load<P: Into<PathBuf>>(path: P) -> Self {
+        let archive: Self = serialise::load_json(path);
+        archive.check();
+        archive
+    }
+
+    fn check(&self) {
+        for axis in &self.axes {
+            axis.check();
+        }
+        let size: usize = self.axes.iter().map(|a| a.bins).product();
+        assert_eq!(self.cells.len(), size, "one cell per bin combination");
+    }
+
+    pub fn save<P: Into<PathBuf>>(&self, path: P) {
+        serialise::save_json(path, self)
+    }
+
+    pub fn axes(&self) -> &[Axis] {
+        &self.axes
+    }
+
+    fn cell_index(&self, features: &[Coord]) -> usize {
+        self.axes
+            .iter()
+            .zip(features)
+            .fold(0, |index, (axis, value)| {
+                index * axis.bins + axis.bin(*value)
+            })
+    }
+
+    /// Bin along each axis of the cell at the given index
+    fn cell_bins(&self, mut index: usize) -> Vec<usize> {
+        let mut bins = vec![0; self.axes.len()];
+        for (bin, axis) in bins.iter_mut().zip(&self.axes).rev() {
+            *bin = index % axis.bins;
+            index /= axis.bins;
+        }
+        bins
+    }
+
+    /// Keeps the individual if its cell is empty or it beats the elite there, returning whether
+    /// it was kept
+    pub fn insert(&mut self, tree: BodyTree, score: Score, features: Vec<Coord>) -> bool {
+        assert_eq!(features.len(), self.axes.len(), "one feature per axis");
+        let index = self.cell_index(&features);
+        let cell = &mut self.cells[index];
+        if cell.as_ref().map_or(false, |elite| elite.score >= score) {
+            return false;
+        }
+
+        *cell = Some(Elite {
+            tree,
+            score,
+            features,
+        });
+        true
+    }
+
+    /// Evaluates each tree with a fresh fitness and inserts it, returning how many were kept.
+    /// Invalid creatures are dropped.
+    pub fn add<F, M>(
+        &mut self,
+        population: Population,
+        settings: &EvaluationSettings,
+        fitness: M,
+    ) -> usize
+    where
+        F: Fitness + 'static,
+        M: Fn() -> F,
+    {
+        let mut kept = 0;
+        for tree in population {
+            if let Some((score, features)) =
+                evaluate_features(&tree, settings, &self.axes, fitness())
+            {
+                if self.insert(tree, score, features) {
+                    kept += 1;
+                }
+            }
+        }
+        kept
+    }
+
+    pub fn elites(&self) -> impl Iterator<Item = &Elite> {
+        self.cells.iter().filter_map(|c| c.as_ref())
+    }
+
+    /// Number of occupied cells
+    pub fn len(&self) -> usize {
+        self.elites().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fraction of cells occupied
+    pub fn coverage(&self) -> Coord {
+        self.len() as Coord / self.cells.len() as Coord
+    }
+
+    /// Mutated clones of elites from randomly chosen occupied cells
+    pub fn offspring<R: Rng>(
+        &self,
+        count: usize,
+        mut_rate: f64,
+        mut_max: f64,
+        rng: &mut R,
+    ) -> Population {
+        let elites: Vec<&Elite> = self.elites().collect();
+        if elites.is_empty() {
+            return Vec::new();
+        }
+
+        (0..count)
+            .map(|_| {
+                let mut child = elites[rng.gen_range(0, elites.len())].tree.clone();
+                child.mutate(mut_rate, mut_max);
+                child
+            })
+            .collect()
+    }
+
+    /// One row per cell with the start of its bin along each axis, and the elite's score if the
+    /// cell is occupied
+    pub fn save_heatmap<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_heatmap(BufWriter::new(File::create(path)?))
+    }
+
+    fn write_heatmap<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for axis in &self.axes {
+            write!(w, "{},", axis.feature.name())?;
+        }
+        writeln!(w, "score")?;
+
+        for (index, cell) in self.cells.iter().enumerate() {
+            for (axis, bin) in self.axes.iter().zip(self.cell_bins(index)) {
+                write!(w, "{},", axis.bin_start(bin))?;
+            }
+            match cell {
+                Some(elite) => writeln!(w, "{}", elite.score)?,
+                None => writeln!(w)?,
+            }
+        }
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body_tree::body::def::new_cuboid;
+
+    fn tree() -> BodyTree {
+        BodyTree::with_root(new_cuboid(
+            (1.0, 1.0, 1.0),
+            (0.0, 0.0, 0.0),
+            (0.0, 0.0, 0.0),
+        ))
+    }
+
+    fn archive() -> Archive {
+        Archive::new(vec![
+            Axis::new(Feature::PartCount, 0.0, 4.0, 4),
+            Axis::new(Feature::BodyHeight, 0.0, 2.0, 2),
+        ])
+    }
+
+    #[test]
+    fn elites() {
+        let mut archive = archive();
+        assert!(archive.insert(tree(), 1.0, vec![1.0, 0.5]));
+        assert!(!archive.insert(tree(), 0.5, vec![1.5, 0.1]));
+        assert!(archive.insert(tree(), 2.0, vec![1.5, 0.1]));
+        assert!(archive.insert(tree(), 1.0, vec![10.0, 10.0]));
+        assert_eq!(archive.len(), 2);
+        assert_eq!(archive.coverage(), 0.25);
+
+        let mut rng = ::rand::thread_rng();
+        assert_eq!(archive.offspring(5, 0.2, 0.05, &mut rng).len(), 5);
+    }
+
+    #[test]
+    fn heatmap() {
+        let mut archive = archive();
+        archive.insert(tree(), 3.0, vec![3.0, 1.0]);
+
+        let mut csv = Vec::new();
+        archive.write_heatmap(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 8);
+        assert_eq!(lines[0], "part_count,body_height,score");
+        assert_eq!(lines[1], "0,0,");
+        assert_eq!(lines[2], "0,1,");
+        assert_eq!(lines[8], "3,1,3");
+    }
+
+    #[test]
+    #[should_panic(expected = "bad axis")]
+    fn empty_axis() {
+        let json = r#"{
+            "axes": [{"feature": "PartCount", "min": 0.0, "max": 4.0, "bins": 0}],
+            "cells": []
+        }"#;
+        let archive: Archive = ::serde_json::from_str(json).unwrap();
+        archive.check();
+    }
+}
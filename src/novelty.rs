@@ -0,0 +1,266 @@
+//! Novelty search, which rewards behaving differently rather than scoring well, so the
+//! population keeps exploring instead of converging on the first trick that works.
+//!
+//! Every evaluation produces a behaviour descriptor, and individuals are scored by their mean
+//! distance to the nearest behaviours among the rest of the population and an archive of
+//! past novel behaviours.
+
+use rand::Rng;
+use std::cmp::Ordering;
+
+use body_tree::tree::BodyTree;
+use body_tree::{Coord, Population};
+use evaluate::{self, EvaluationSettings, Score};
+use fitness::Fitness;
+use physics::World;
+
+/// A point in behaviour space
+pub type Behaviour = Vec<Coord>;
+
+/// What is recorded of an evaluation as its behaviour
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Descriptor {
+    /// Where the root ends up
+    FinalPosition,
+    /// Positions of the root evenly spaced over the evaluation including settling, from the
+    /// first to the last
+    Trajectory { samples: usize },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NoveltySettings {
+    pub descriptor: Descriptor,
+    /// Number of nearest neighbours that novelty is measured against
+    pub neighbours: usize,
+    /// Novelty needed to enter the archive, adjusted as the search goes
+    pub archive_threshold: Score,
+    /// More additions than this in one generation raise the threshold
+    pub max_additions: usize,
+    /// This many generations without additions lower the threshold
+    pub stagnation: usize,
+    pub mut_rate: f64,
+    pub mut_max: f64,
+}
+
+pub struct NoveltySearch {
+    settings: NoveltySettings,
+    archive: Vec<Behaviour>,
+    threshold: Score,
+    /// Generations since anything was archived
+    stagnant_for: usize,
+}
+
+impl Default for NoveltySettings {
+    fn default() -> Self {
+        Self {
+            descriptor: Descriptor::FinalPosition,
+            neighbours: 15,
+            archive_threshold: 1.0,
+            max_additions: 4,
+            stagnation: 5,
+            mut_rate: 0.2,
+            mut_max: 0.05,
+        }
+    }
+}
+
+/// The creature's behaviour, None if it's invalid
+pub fn evaluate_behaviour(
+    tree: &BodyTree,
+    settings: &EvaluationSettings,
+    descriptor: Descriptor,
+) -> Option<Behaviour> {
+    let mut positions = Vec::new();
+    let no_objectives: Vec<Box<Fitness>> = Vec::new();
+    evaluate::evaluate_observed(tree, settings, no_objectives, &mut |world: &World| {
+        let root = world
+            .creatures()
+            .next()
+            .and_then(|c| world.creature(c))
+            .and_then(|c| c.root_position());
+        if let Some(root) = root {
+            positions.push(root.translation.vector);
+        }
+    })?;
+
+    let behaviour = match descriptor {
+        Descriptor::FinalPosition => positions
+            .last()
+            .map_or_else(Vec::new, |p| vec![p.x, p.y, p.z]),
+        Descriptor::Trajectory { samples } => {
+            let last = positions.len().saturating_sub(1);
+            let spacing = samples.saturating_sub(1).max(1);
+            (0..samples)
+                .filter_map(|i| positions.get(i * last / spacing))
+                .flat_map(|p| vec![p.x, p.y, p.z])
+                .collect()
+        }
+    };
+    Some(behaviour)
+}
+
+fn distance(a: &[Coord], b: &[Coord]) -> Coord {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<Coord>()
+        .sqrt()
+}
+
+/// Mean distance to the `k` nearest of `others`
+pub fn novelty<'a, I>(behaviour: &[Coord], others: I, k: usize) -> Score
+where
+    I: Iterator<Item = &'a Behaviour>,
+{
+    let mut distances: Vec<Coord> = others.map(|o| distance(behaviour, o)).collect();
+    distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    distances.truncate(k);
+    if distances.is_empty() {
+        0.0
+    } else {
+        distances.iter().sum::<Coord>() / distances.len() as Coord
+    }
+}
+
+impl NoveltySearch {
+    pub fn new(settings: NoveltySettings) -> Self {
+        Self {
+            threshold: settings.archive_threshold,
+            settings,
+            archive: Vec::new(),
+            stagnant_for: 0,
+        }
+    }
+
+    pub fn archive(&self) -> &[Behaviour] {
+        &self.archive
+    }
+
+    /// Current novelty needed to enter the archive
+    pub fn threshold(&self) -> Score {
+        self.threshold
+    }
+
+    /// Novelty of each behaviour in population order, against the rest of the population and
+    /// the archive. Invalid creatures score `INVALID_SCORE`. Behaviours novel enough are then
+    /// archived.
+    pub fn score(&mut self, behaviours: &[Option<Behaviour>]) -> Vec<Score> {
+        let scores: Vec<Score> = behaviours
+            .iter()
+            .enumerate()
+            .map(|(i, behaviour)| match behaviour {
+                Some(behaviour) => {
+                    let population = behaviours
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != i)
+                        .filter_map(|(_, b)| b.as_ref());
+                    novelty(
+                        behaviour,
+                        population.chain(self.archive.iter()),
+                        self.settings.neighbours,
+                    )
+                }
+                None => evaluate::INVALID_SCORE,
+            })
+            .collect();
+
+        let mut added = 0;
+        for (behaviour, score) in behaviours.iter().zip(&scores) {
+            if let Some(behaviour) = behaviour {
+                if *score > self.threshold {
+                    self.archive.push(behaviour.clone());
+                    added += 1;
+                }
+            }
+        }
+
+        if added > self.settings.max_additions {
+            self.threshold *= 1.2;
+        }
+        if added == 0 {
+            self.stagnant_for += 1;
+            if self.stagnant_for >= self.settings.stagnation {
+                self.threshold *= 0.95;
+                self.stagnant_for = 0;
+            }
+        } else {
+            self.stagnant_for = 0;
+        }
+
+        scores
+    }
+
+    /// Evaluates and scores the population, returning the next generation bred by binary
+    /// tournament on novelty, along with the novelty of the current one
+    pub fn generation<R: Rng>(
+        &mut self,
+        population: &Population,
+        settings: &EvaluationSettings,
+        rng: &mut R,
+    ) -> (Population, Vec<Score>) {
+        if population.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+
+        let behaviours: Vec<Option<Behaviour>> = population
+            .iter()
+            .map(|tree| evaluate_behaviour(tree, settings, self.settings.descriptor))
+            .collect();
+        let scores = self.score(&behaviours);
+
+        let next = (0..population.len())
+            .map(|_| {
+                let a = rng.gen_range(0, population.len());
+                let b = rng.gen_range(0, population.len());
+                let winner = if scores[a] >= scores[b] { a } else { b };
+                let mut child = population[winner].clone();
+                child.mutate(self.settings.mut_rate, self.settings.mut_max);
+                child
+            })
+            .collect();
+        (next, scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_neighbours() {
+        let others = vec![vec![1.0, 0.0], vec![0.0, 2.0], vec![10.0, 0.0]];
+        assert_eq!(novelty(&[0.0, 0.0], others.iter(), 2), 1.5);
+        assert_eq!(novelty(&[0.0, 0.0], others.iter(), 10), 13.0 / 3.0);
+        let none: Vec<Behaviour> = Vec::new();
+        assert_eq!(novelty(&[0.0, 0.0], none.iter(), 2), 0.0);
+    }
+
+    #[test]
+    fn archiving() {
+        let mut search = NoveltySearch::new(NoveltySettings {
+            neighbours: 1,
+            archive_threshold: 2.0,
+            ..NoveltySettings::default()
+        });
+
+        let behaviours = vec![Some(vec![0.0]), Some(vec![1.0]), Some(vec![5.0]), None];
+        let scores = search.score(&behaviours);
+        assert_eq!(scores, vec![1.0, 1.0, 4.0, evaluate::INVALID_SCORE]);
+        assert_eq!(search.archive(), &[vec![5.0]]);
+
+        // the same behaviour again is no longer novel, as it's archived
+        let scores = search.score(&[Some(vec![5.0])]);
+        assert_eq!(scores, vec![0.0]);
+        assert_eq!(search.archive().len(), 1);
+    }
+
+    #[test]
+    fn empty_generation() {
+        let mut search = NoveltySearch::new(NoveltySettings::default());
+        let settings = EvaluationSettings::default();
+        let (next, scores) = search.generation(&Vec::new(), &settings, &mut ::rand::thread_rng());
+        assert!(next.is_empty() && scores.is_empty());
+        assert_eq!(search.stagnant_for, 0);
+    }
+}
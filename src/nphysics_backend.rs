@@ -0,0 +1,246 @@
+//! `PhysicsBackend` on nphysics, with each creature a single multibody.
+
+use nalgebra::{zero, Isometry3, Vector3};
+use ncollide3d::events::ContactEvent;
+use ncollide3d::shape::{Compound, Cuboid, ShapeHandle};
+use ncollide3d::world::CollisionGroups;
+use nphysics3d::algebra::Force3;
+use nphysics3d::joint::{FixedJoint, FreeJoint, Joint, RevoluteJoint};
+use nphysics3d::object::{BodyHandle, ColliderHandle, Material};
+use nphysics3d::volumetric::Volumetric;
+use nphysics3d::world;
+
+use backend::{ColliderShape, CollisionFilter, Contact, LinkJoint, PhysicsBackend, Velocity};
+use body_tree::Coord;
+use physics::WorldConfig;
+
+pub struct NPhysicsBackend {
+    world: world::World<Coord>,
+    config: WorldConfig,
+    gravity: Vector3<Coord>,
+    /// Forces from `apply_force`, waiting for the next step
+    forces: Vec<(BodyHandle, Force3<Coord>)>,
+}
+
+impl NPhysicsBackend {
+    fn create_world(config: &WorldConfig) -> world::World<Coord> {
+        let mut world = world::World::new();
+        world.set_gravity(config.gravity);
+        {
+            let params = world.integration_parameters_mut();
+            params.dt = config.timestep;
+            params.max_velocity_iterations = config.velocity_iterations;
+            params.max_position_iterations = config.position_iterations;
+        }
+        world
+    }
+
+    /// nphysics ignores forces on multibody links, so each is applied instead as the change in
+    /// velocity it would have made over the step that has just run
+    fn apply_queued_forces(&mut self) {
+        let dt = self.config.timestep;
+        for (body, force) in self.forces.drain(..) {
+            let link = match self.world.multibody_link(body) {
+                Some(link) => link.id(),
+                None => continue,
+            };
+
+            self.world.activate_body(body);
+            let multibody = self
+                .world
+                .multibody_mut(body)
+                .expect("link without multibody");
+            let mut acceleration = vec![0.0; multibody.ndofs()];
+            multibody.inv_mass_mul_force(link, &force, &mut acceleration);
+            for (vel, acc) in multibody
+                .generalized_velocity_slice_mut()
+                .iter_mut()
+                .zip(acceleration)
+            {
+                *vel += acc * dt;
+            }
+        }
+    }
+
+    fn add_multibody_link<J: Joint<Coord>>(
+        &mut self,
+        parent: BodyHandle,
+        joint: J,
+        parent_shift: Vector3<Coord>,
+        half_extents: &Vector3<Coord>,
+    ) -> BodyHandle {
+        let shape = Cuboid::new(*half_extents);
+        let inertia = shape.inertia(1.0);
+        let com = shape.center_of_mass();
+        self.world
+            .add_multibody_link(parent, joint, parent_shift, zero(), inertia, com)
+    }
+}
+
+fn collision_groups(filter: &CollisionFilter) -> CollisionGroups {
+    let mut groups = CollisionGroups::new();
+    groups.set_membership(&CollisionFilter::groups(filter.membership));
+    groups.set_whitelist(&CollisionFilter::groups(filter.whitelist));
+    groups.set_blacklist(&CollisionFilter::groups(filter.blacklist));
+    groups
+}
+
+impl PhysicsBackend for NPhysicsBackend {
+    type Body = BodyHandle;
+    type Collider = ColliderHandle;
+
+    fn new(config: &WorldConfig) -> Self {
+        Self {
+            world: Self::create_world(config),
+            config: config.clone(),
+            gravity: config.gravity,
+            forces: Vec::new(),
+        }
+    }
+
+    fn set_gravity(&mut self, gravity: Vector3<Coord>) {
+        self.gravity = gravity;
+        self.world.set_gravity(gravity);
+    }
+
+    fn step(&mut self) {
+        self.world.step();
+        self.apply_queued_forces();
+    }
+
+    fn clear(&mut self) {
+        self.forces.clear();
+        self.world = Self::create_world(&self.config);
+        self.world.set_gravity(self.gravity);
+    }
+
+    fn ground(&self) -> BodyHandle {
+        BodyHandle::ground()
+    }
+
+    fn add_link(
+        &mut self,
+        parent: BodyHandle,
+        joint: &LinkJoint,
+        half_extents: &Vector3<Coord>,
+    ) -> BodyHandle {
+        match *joint {
+            LinkJoint::Free(pos) => {
+                self.add_multibody_link(parent, FreeJoint::new(pos), zero(), half_extents)
+            }
+            LinkJoint::Fixed(pos) => {
+                self.add_multibody_link(parent, FixedJoint::new(pos), zero(), half_extents)
+            }
+            LinkJoint::Revolute {
+                offset,
+                motor_speed,
+                max_torque,
+            } => {
+                let mut rev = RevoluteJoint::new(Vector3::x_axis(), 0.0);
+                rev.enable_angular_motor();
+                rev.set_desired_angular_motor_velocity(motor_speed);
+                rev.set_max_angular_motor_torque(max_torque);
+                rev.disable_min_angle();
+                rev.disable_max_angle();
+
+                self.add_multibody_link(parent, rev, offset, half_extents)
+            }
+        }
+    }
+
+    fn remove_body(&mut self, body: BodyHandle) {
+        self.world.remove_bodies(&[body]);
+    }
+
+    fn add_collider(
+        &mut self,
+        body: BodyHandle,
+        shape: &ColliderShape,
+        position: &Isometry3<Coord>,
+        margin: Coord,
+        filter: &CollisionFilter,
+    ) -> ColliderHandle {
+        let shape = match shape {
+            ColliderShape::Cuboid(half_extents) => ShapeHandle::new(Cuboid::new(*half_extents)),
+            ColliderShape::Cuboids(cuboids) => {
+                let shapes = cuboids
+                    .iter()
+                    .map(|(pos, half_extents)| (*pos, ShapeHandle::new(Cuboid::new(*half_extents))))
+                    .collect();
+                ShapeHandle::new(Compound::new(shapes))
+            }
+        };
+
+        let collider = self
+            .world
+            .add_collider(margin, shape, body, *position, Material::default());
+        self.world
+            .collision_world_mut()
+            .set_collision_groups(collider, collision_groups(filter));
+        collider
+    }
+
+    fn collider_position(&self, collider: ColliderHandle) -> Option<Isometry3<Coord>> {
+        self.world.collider(collider).map(|c| *c.position())
+    }
+
+    fn collider_body(&self, collider: ColliderHandle) -> Option<BodyHandle> {
+        self.world.collider(collider).map(|c| c.data().body())
+    }
+
+    fn body_position(&self, body: BodyHandle) -> Isometry3<Coord> {
+        self.world.body_part(body).position()
+    }
+
+    fn body_velocity(&self, body: BodyHandle) -> Velocity {
+        let vel = self.world.body_part(body).velocity();
+        Velocity {
+            linear: vel.linear,
+            angular: vel.angular,
+        }
+    }
+
+    fn is_active(&self, body: BodyHandle) -> bool {
+        self.world.body(body).is_active()
+    }
+
+    fn apply_force(&mut self, body: BodyHandle, force: Vector3<Coord>, torque: Vector3<Coord>) {
+        self.forces.push((body, Force3::new(force, torque)));
+    }
+
+    fn joint_angle(&self, body: BodyHandle) -> Option<Coord> {
+        let link = self.world.multibody_link(body)?;
+        let joint = link.joint().downcast_ref::<RevoluteJoint<Coord>>().ok()?;
+        Some(joint.angle())
+    }
+
+    fn motor_impulse(&self, body: BodyHandle) -> Option<Coord> {
+        let link = self.world.multibody_link(body)?;
+        let joint = link.joint().downcast_ref::<RevoluteJoint<Coord>>().ok()?;
+        if !joint.is_angular_motor_enabled() {
+            return Some(0.0);
+        }
+
+        // the motor's impulse is cached first of the joint's degree of freedom
+        Some(link.multibody().impulses()[link.impulse_id()])
+    }
+
+    fn disable_motor(&mut self, body: BodyHandle) {
+        if let Some(mut link) = self.world.multibody_link_mut(body) {
+            if let Ok(joint) = link.joint_mut().downcast_mut::<RevoluteJoint<Coord>>() {
+                joint.disable_angular_motor();
+            }
+        }
+    }
+
+    fn contact_events(&self) -> Vec<Contact<ColliderHandle>> {
+        self.world
+            .contact_events()
+            .iter()
+            .map(|event| match *event {
+                ContactEvent::Started(a, b) => Contact::Started(a, b),
+                ContactEvent::Stopped(a, b) => Contact::Stopped(a, b),
+            })
+            .collect()
+    }
+}
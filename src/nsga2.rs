@@ -0,0 +1,330 @@
+//! NSGA-II selection, for trading off several objectives without weighting them against each
+//! other.
+//!
+//! Individuals are ranked into fronts by non-dominated sorting, and ties within a front are
+//! broken by crowding distance so that the survivors stay spread along the front. Every
+//! objective is maximised, as with `fitness::Fitness`. Invalid individuals have no scores, and
+//! are dominated by every valid one.
+
+use rand::Rng;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use body_tree::tree::BodyTree;
+use body_tree::{serialise, Population};
+use evaluate::Score;
+
+/// Score of each objective for a single individual
+pub type Objectives = Vec<Score>;
+
+/// Objectives of an individual, None if it's invalid
+pub type Scores = Option<Objectives>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rank {
+    /// Index of the individual's front, 0 being the Pareto front
+    pub front: usize,
+    /// Infinite at the edges of the front
+    pub crowding: Score,
+}
+
+/// The non-dominated individuals of a population, with their objectives
+#[derive(Debug, Clone, Default)]
+pub struct ParetoFront {
+    pub members: Population,
+    pub objectives: Vec<Objectives>,
+}
+
+/// Whether `a` is at least as good as `b` in every objective, and better in at least one
+pub fn dominates(a: &[Score], b: &[Score]) -> bool {
+    let mut better = false;
+    for (a, b) in a.iter().zip(b) {
+        if a < b {
+            return false;
+        }
+        better |= a > b;
+    }
+    better
+}
+
+/// Indices of each front, best first. Invalid individuals make up the last front.
+pub fn non_dominated_sort(scores: &[Scores]) -> Vec<Vec<usize>> {
+    let n = scores.len();
+    let mut dominated: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut domination_count = vec![0; n];
+    for i in 0..n {
+        for j in i + 1..n {
+            let (a, b) = match (&scores[i], &scores[j]) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+            if dominates(a, b) {
+                dominated[i].push(j);
+                domination_count[j] += 1;
+            } else if dominates(b, a) {
+                dominated[j].push(i);
+                domination_count[i] += 1;
+            }
+        }
+    }
+
+    let valid = |i: &usize| scores[*i].is_some();
+    let mut fronts = Vec::new();
+    let mut current: Vec<usize> = (0..n)
+        .filter(|i| valid(i) && domination_count[*i] == 0)
+        .collect();
+    while !current.is_empty() {
+        let mut next = Vec::new();
+        for i in &current {
+            for j in &dominated[*i] {
+                domination_count[*j] -= 1;
+                if domination_count[*j] == 0 {
+                    next.push(*j);
+                }
+            }
+        }
+        fronts.push(current);
+        current = next;
+    }
+
+    let invalid: Vec<usize> = (0..n).filter(|i| !valid(i)).collect();
+    if !invalid.is_empty() {
+        fronts.push(invalid);
+    }
+    fronts
+}
+
+/// Crowding distance of each member of a front, in the same order. Zero for the front of
+/// invalid individuals.
+pub fn crowding_distance(scores: &[Scores], front: &[usize]) -> Vec<Score> {
+    let mut distance = vec![0.0; front.len()];
+    let objectives: Vec<&Objectives> = match front.iter().map(|i| scores[*i].as_ref()).collect() {
+        Some(objectives) => objectives,
+        None => return distance,
+    };
+    let objective_count = objectives.first().map_or(0, |o| o.len());
+    let mut order: Vec<usize> = (0..front.len()).collect();
+
+    for m in 0..objective_count {
+        let value = |i: usize| objectives[i][m];
+        order.sort_by(|a, b| value(*a).partial_cmp(&value(*b)).unwrap_or(Ordering::Equal));
+
+        let (first, last) = (order[0], order[order.len() - 1]);
+        distance[first] = ::std::f64::INFINITY;
+        distance[last] = ::std::f64::INFINITY;
+
+        let range = value(last) - value(first);
+        if range <= 0.0 {
+            continue;
+        }
+        for w in order.windows(3) {
+            distance[w[1]] += (value(w[2]) - value(w[0])) / range;
+        }
+    }
+    distance
+}
+
+/// Front and crowding distance of every individual, in population order
+pub fn rank(scores: &[Scores]) -> Vec<Rank> {
+    let mut ranks = vec![
+        Rank {
+            front: 0,
+            crowding: 0.0,
+        };
+        scores.len()
+    ];
+    for (front_index, front) in non_dominated_sort(scores).iter().enumerate() {
+        let crowding = crowding_distance(scores, front);
+        for (i, crowding) in front.iter().zip(crowding) {
+            ranks[*i] = Rank {
+                front: front_index,
+                crowding,
+            };
+        }
+    }
+    ranks
+}
+
+/// Crowded comparison: lower fronts first, then the least crowded
+fn compare(a: &Rank, b: &Rank) -> Ordering {
+    a.front.cmp(&b.front).then_with(|| {
+        b.crowding
+            .partial_cmp(&a.crowding)
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// Indices of the `count` best individuals by crowded comparison, best first
+pub fn select(scores: &[Scores], count: usize) -> Vec<usize> {
+    let ranks = rank(scores);
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|a, b| compare(&ranks[*a], &ranks[*b]));
+    order.truncate(count);
+    order
+}
+
+/// Binary tournament by crowded comparison, returning the winner's index, None if there's no
+/// one to choose from
+pub fn tournament<R: Rng>(ranks: &[Rank], rng: &mut R) -> Option<usize> {
+    if ranks.is_empty() {
+        return None;
+    }
+
+    let a = rng.gen_range(0, ranks.len());
+    let b = rng.gen_range(0, ranks.len());
+    match compare(&ranks[a], &ranks[b]) {
+        Ordering::Greater => Some(b),
+        _ => Some(a),
+    }
+}
+
+/// Children bred from parents chosen by tournament, each a mutated clone
+pub fn offspring<R: Rng>(
+    population: &Population,
+    scores: &[Scores],
+    count: usize,
+    mut_rate: f64,
+    mut_max: f64,
+    rng: &mut R,
+) -> Population {
+    let ranks = rank(scores);
+    (0..count)
+        .filter_map(|_| tournament(&ranks, rng))
+        .map(|parent| {
+            let mut child = population[parent].clone();
+            child.mutate(mut_rate, mut_max);
+            child
+        })
+        .collect()
+}
+
+/// The survivors of parents and their children, which must be scored in that order
+pub fn next_generation(
+    parents: Population,
+    children: Population,
+    scores: &[Scores],
+) -> (Population, Vec<Scores>) {
+    let count = parents.len();
+    let mut combined: Vec<Option<BodyTree>> =
+        parents.into_iter().chain(children).map(Some).collect();
+    assert_eq!(
+        combined.len(),
+        scores.len(),
+        "every individual needs scores"
+    );
+
+    select(scores, count)
+        .into_iter()
+        .map(|i| {
+            let tree = combined[i].take().expect("selected twice");
+            (tree, scores[i].clone())
+        })
+        .unzip()
+}
+
+impl ParetoFront {
+    /// Empty if every individual is invalid
+    pub fn of(population: &Population, scores: &[Scores]) -> Self {
+        let mut front = Self::default();
+        let first = non_dominated_sort(scores).into_iter().next();
+        for i in first.unwrap_or_default() {
+            if let Some(ref objectives) = scores[i] {
+                front.members.push(population[i].clone());
+                front.objectives.push(objectives.clone());
+            }
+        }
+        front
+    }
+
+    /// Saved as a normal population, so it can be loaded into the renderer
+    pub fn save<P: Into<PathBuf>>(&self, path: P) {
+        serialise::save(path, &self.members)
+    }
+
+    /// One row per member in the same order as the saved population
+    pub fn save_objectives<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(path)?);
+        for (i, objectives) in self.objectives.iter().enumerate() {
+            write!(f, "{}", i)?;
+            for score in objectives {
+                write!(f, ",{}", score)?;
+            }
+            writeln!(f)?;
+        }
+        f.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scores() -> Vec<Scores> {
+        vec![
+            Some(vec![1.0, 5.0]),
+            Some(vec![2.0, 4.0]),
+            Some(vec![3.0, 3.0]),
+            Some(vec![2.0, 2.0]),
+            Some(vec![1.0, 1.0]),
+            Some(vec![5.0, 1.0]),
+        ]
+    }
+
+    #[test]
+    fn domination() {
+        assert!(dominates(&[2.0, 2.0], &[1.0, 2.0]));
+        assert!(!dominates(&[2.0, 2.0], &[2.0, 2.0]));
+        assert!(!dominates(&[3.0, 1.0], &[1.0, 3.0]));
+    }
+
+    #[test]
+    fn fronts() {
+        let fronts = non_dominated_sort(&scores());
+        assert_eq!(fronts, vec![vec![0, 1, 2, 5], vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn crowding() {
+        let scores = scores();
+        let front = vec![0, 1, 2, 5];
+        let distance = crowding_distance(&scores, &front);
+        assert!(distance[0].is_infinite());
+        assert!(distance[3].is_infinite());
+        // (3 - 1) / 4 + (5 - 3) / 4 for index 1, (5 - 2) / 4 + (4 - 1) / 4 for index 2
+        assert!((distance[1] - 1.0).abs() < 1e-9);
+        assert!((distance[2] - 1.5).abs() < 1e-9);
+
+        // the edges survive, then the least crowded
+        assert_eq!(select(&scores, 3), vec![0, 5, 2]);
+    }
+
+    #[test]
+    fn empty() {
+        let mut rng = ::rand::thread_rng();
+        assert_eq!(tournament(&[], &mut rng), None);
+        assert!(offspring(&Vec::new(), &[], 5, 0.2, 0.05, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn invalid_last() {
+        let mut scores = scores();
+        scores.insert(1, None);
+        scores.push(None);
+
+        let fronts = non_dominated_sort(&scores);
+        assert_eq!(fronts.last(), Some(&vec![1, 7]));
+        assert_eq!(fronts[0], vec![0, 2, 3, 6]);
+        assert_eq!(crowding_distance(&scores, &[1, 7]), vec![0.0, 0.0]);
+
+        // even the worst valid individual survives over them
+        assert_eq!(select(&scores, 6), vec![0, 6, 3, 2, 4, 5]);
+
+        let pop: Population = (0..8).map(|_| BodyTree::default()).collect();
+        assert_eq!(ParetoFront::of(&pop, &scores).members.len(), 4);
+        assert!(ParetoFront::of(&pop[..1].to_vec(), &[None])
+            .members
+            .is_empty());
+    }
+}
@@ -1,19 +1,53 @@
 use nalgebra::{zero, Isometry3, Point3, Translation, UnitQuaternion, Vector3};
-use ncollide3d::bounding_volume::{HasBoundingVolume, AABB};
-use ncollide3d::shape::{Cuboid, ShapeHandle};
-use nphysics3d::joint::{FixedJoint, FreeJoint, Joint, RevoluteJoint};
-use nphysics3d::object::{Body, BodyHandle, Collider, ColliderHandle, Material};
-use nphysics3d::volumetric::Volumetric;
-use nphysics3d::world;
+use ncollide3d::bounding_volume::{BoundingVolume, HasBoundingVolume, AABB};
+use ncollide3d::shape::Cuboid;
 use rand::{self, Rng};
+use std::collections::HashMap;
 
+use backend::{ColliderShape, CollisionFilter, Contact, LinkJoint, PhysicsBackend, Velocity};
 use body_tree::body::def::RangedParam;
-use body_tree::tree::TreeRealiser;
+use body_tree::tree::{BodyTree, TreeRealiser};
 use body_tree::{body::def, Coord};
+use fluid;
+use nphysics_backend::NPhysicsBackend;
+use placement;
+use sanity::{self, Invalidity, PartSample, SanityLimits};
+use terrain::{self, Terrain};
 
-const COLLIDER_MARGIN: Coord = 0.01;
+/// Collision group of the ground
+const GROUP_GROUND: usize = 0;
 
-#[derive(Debug, Copy, Clone)]
+/// Each creature has a group per level of its tree, modulo this
+const GROUPS_PER_CREATURE: usize = 4;
+
+/// Isolated creatures are told apart by a binary code of their handle, with a group for each
+/// value of each bit, so creatures only share a code if their handles are a multiple of
+/// `1 << CODE_BITS` apart
+const CODE_BITS: usize = 12;
+
+/// First of the level groups that isolated creatures share, after the code groups
+const GROUP_LEVELS: usize = 1 + 2 * CODE_BITS;
+
+/// Creatures that aren't isolated cycle through slots of level groups, so those that share
+/// groups are spawned this many creatures apart, and treat each other's parts as their own
+const CREATURE_SLOTS: usize = 7;
+
+/// Gap left between a grounded creature's lowest point and the ground
+pub const DEFAULT_GROUND_CLEARANCE: Coord = 0.05;
+
+/// Which parts of the same creature can collide with each other
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SelfCollision {
+    Disabled,
+    /// Parts one level apart in the tree never collide, which includes all joined parts.
+    /// Levels are compared modulo `GROUPS_PER_CREATURE`, and whole levels rather than joined
+    /// pairs are kept apart, so a part also misses its siblings' children, and parts 3 or 5
+    /// levels apart.
+    NonAdjacent,
+    Full,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Colour {
     pub r: f32,
     pub g: f32,
@@ -26,141 +60,660 @@ const COLOUR_GROUND: Colour = Colour {
     b: 0.1,
 };
 
-#[derive(Debug, Copy, Clone)]
+const COLOUR_TERRAIN: Colour = Colour {
+    r: 0.3,
+    g: 0.25,
+    b: 0.2,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ObjectShape {
     Cuboid(Vector3<Coord>),
     Plane(Point3<Coord>, Vector3<Coord>, Coord),
+    /// Cuboids with their half extents, relative to the object
+    Blocks(Vec<(Isometry3<Coord>, Vector3<Coord>)>),
 }
 
 #[derive(Debug)]
 pub struct WorldObject {
     pub shape: ObjectShape,
     pub colour: Colour,
+    /// None for the ground
+    pub creature: Option<CreatureHandle>,
 }
 
-pub struct World {
-    physics: world::World<Coord>,
-    objects: Vec<(ColliderHandle, WorldObject)>,
-    ground_collider: Option<ColliderHandle>,
+/// A single realised body tree in a `World`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CreatureHandle(usize);
+
+struct CreatureBody<B: PhysicsBackend> {
+    spawn_pos: Vector3<Coord>,
+    /// In realisation order, so the root is first
+    parts: Vec<CreaturePart<B>>,
+    /// Set once the simulation of this creature has blown up
+    invalid: Option<Invalidity>,
+    /// Kinetic energy per unit mass at the last sanity check
+    kinetic_energy: Coord,
+    /// Joules spent by motors so far
+    energy_used: Coord,
+    /// Motors are disabled once the energy cap is reached
+    exhausted: bool,
+}
+
+struct CreaturePart<B: PhysicsBackend> {
+    collider: B::Collider,
+    body: B::Body,
+    /// Index into the creature's parts, None for the root
+    parent: Option<usize>,
+    joint: def::Joint,
+    mass: Coord,
+    half_extents: Vector3<Coord>,
+}
+
+/// One side of a contact
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Contactee {
+    /// The ground or any terrain on it
+    Ground,
+    Part {
+        creature: CreatureHandle,
+        /// Index into the creature's parts, in realisation order so the root is 0
+        part: usize,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ContactEvent {
+    Started(Contactee, Contactee),
+    Stopped(Contactee, Contactee),
+}
+
+/// Read-only view of a creature's current physical state
+pub struct Creature<'w, B: 'w + PhysicsBackend = NPhysicsBackend> {
+    world: &'w World<B>,
+    body: &'w CreatureBody<B>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorldConfig {
+    /// Seconds per tick
+    pub timestep: Coord,
+    pub gravity: Vector3<Coord>,
+    pub velocity_iterations: usize,
+    pub position_iterations: usize,
+    /// Half size of the ground cuboid
+    pub ground_size: Coord,
+    pub collider_margin: Coord,
+    /// Creatures only collide with the ground and themselves
+    pub isolate_creatures: bool,
+    pub self_collision: SelfCollision,
+    /// Checked for every creature after each tick, None to disable
+    pub sanity: Option<SanityLimits>,
+    /// Joules each creature's motors can spend before they are switched off
+    pub energy_cap: Option<Coord>,
+}
+
+/// Creatures and terrain simulated by any physics backend, nphysics by default
+pub struct World<B: PhysicsBackend = NPhysicsBackend> {
+    physics: B,
+    config: WorldConfig,
+    objects: Vec<(B::Collider, WorldObject)>,
+    creatures: Vec<(CreatureHandle, CreatureBody<B>)>,
+    ground_collider: Option<B::Collider>,
+    terrain: Terrain,
+    /// Generated from the terrain whenever the world is cleared
+    terrain_blocks: Vec<terrain::Block>,
+    creature_count: usize,
+    environment: Environment,
+    generation: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Environment {
+    Land,
+    /// Neutrally buoyant, with drag on every part
+    Water {
+        drag: Coord,
+    },
 }
 
 impl WorldObject {
-    fn new(shape: ObjectShape, colour: Colour) -> Self {
-        Self { shape, colour }
+    fn new(shape: ObjectShape, colour: Colour, creature: Option<CreatureHandle>) -> Self {
+        Self {
+            shape,
+            colour,
+            creature,
+        }
+    }
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            timestep: 1.0 / 60.0,
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            velocity_iterations: 50,
+            position_iterations: 10,
+            ground_size: 100.0,
+            collider_margin: 0.01,
+            isolate_creatures: true,
+            self_collision: SelfCollision::NonAdjacent,
+            sanity: Some(SanityLimits::default()),
+            energy_cap: None,
+        }
     }
 }
 
-impl Default for World {
+impl<B: PhysicsBackend> Default for World<B> {
     fn default() -> Self {
-        let mut world = world::World::new();
-        world.set_gravity(Vector3::new(0.0, -9.81, 0.0));
+        Self::new(WorldConfig::default())
+    }
+}
+
+impl<B: PhysicsBackend> World<B> {
+    pub fn new(config: WorldConfig) -> Self {
         Self {
-            physics: world,
+            physics: B::new(&config),
+            config,
             objects: Vec::new(),
+            creatures: Vec::new(),
             ground_collider: None,
+            terrain: Terrain::default(),
+            terrain_blocks: Vec::new(),
+            creature_count: 0,
+            environment: Environment::Land,
+            generation: 0,
         }
     }
-}
 
-impl World {
+    pub fn config(&self) -> &WorldConfig {
+        &self.config
+    }
+
+    /// Incremented every time the world is cleared, after which collider and creature handles
+    /// may be reused
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
     fn register_object(
         &mut self,
-        collider: ColliderHandle,
+        collider: B::Collider,
         def: &def::ShapeDefinition,
         colour: Colour,
+        creature: Option<CreatureHandle>,
     ) {
-        let object = WorldObject::new(ObjectShape::from_def(def), colour);
+        let object = WorldObject::new(ObjectShape::from_def(def), colour, creature);
         self.register_created_object(collider, object);
     }
 
-    fn register_created_object(&mut self, collider: ColliderHandle, object: WorldObject) {
+    /// Returns the part's index in the creature
+    fn register_part(&mut self, creature: CreatureHandle, part: CreaturePart<B>) -> usize {
+        let (_, body) = self
+            .creatures
+            .iter_mut()
+            .find(|(c, _)| *c == creature)
+            .expect("Creature doesn't exist");
+        body.parts.push(part);
+        body.parts.len() - 1
+    }
+
+    fn register_created_object(&mut self, collider: B::Collider, object: WorldObject) {
         self.objects.push((collider, object));
     }
 
-    pub fn objects(
-        &self,
-    ) -> impl Iterator<Item = (ColliderHandle, &Collider<Coord>, &WorldObject)> {
+    pub fn objects(&self) -> impl Iterator<Item = (B::Collider, &WorldObject)> {
         self.objects
             .iter()
-            .filter_map(move |(ch, o)| self.physics.collider(*ch).map(|coll| (*ch, coll, o)))
+            .filter(move |(ch, _)| self.physics.collider_position(*ch).is_some())
+            .map(|(ch, o)| (*ch, o))
     }
 
+    /// Every live object with its current position, and whether its body is awake
     pub fn colliders(
         &self,
-    ) -> impl Iterator<Item = (ColliderHandle, &WorldObject, &Collider<Coord>, Body<Coord>)> {
+    ) -> impl Iterator<Item = (B::Collider, &WorldObject, Isometry3<Coord>, bool)> {
         self.objects.iter().filter_map(move |(ch, o)| {
-            self.physics
-                .collider(*ch)
-                .map(|c| (*ch, o, c, self.physics.body(c.data().body())))
+            let position = self.physics.collider_position(*ch)?;
+            let body = self.physics.collider_body(*ch)?;
+            Some((*ch, o, position, self.physics.is_active(body)))
         })
     }
 
-    fn shape(&self, ch: ColliderHandle) -> Option<ObjectShape> {
+    fn shape(&self, ch: B::Collider) -> Option<&ObjectShape> {
         self.objects
             .iter()
-            .find(|(x, _)| x.uid() == ch.uid())
-            .map(|tup| tup.1.shape)
+            .find(|(x, _)| *x == ch)
+            .map(|tup| &tup.1.shape)
+    }
+
+    /// Replaces the terrain, which clears the world
+    pub fn set_terrain(&mut self, terrain: Terrain) {
+        self.terrain = terrain;
+        self.clear();
+    }
+
+    pub fn set_creature_isolation(&mut self, isolate: bool) {
+        self.config.isolate_creatures = isolate;
+    }
+
+    fn new_creature(&mut self, spawn_pos: Vector3<Coord>) -> CreatureHandle {
+        let creature = CreatureHandle(self.creature_count);
+        self.creature_count += 1;
+        self.creatures.push((
+            creature,
+            CreatureBody {
+                spawn_pos,
+                parts: Vec::new(),
+                invalid: None,
+                kinetic_energy: 0.0,
+                energy_used: 0.0,
+                exhausted: false,
+            },
+        ));
+        creature
+    }
+
+    pub fn creatures(&self) -> impl Iterator<Item = CreatureHandle> + '_ {
+        self.creatures.iter().map(|(c, _)| *c)
+    }
+
+    pub fn creature(&self, creature: CreatureHandle) -> Option<Creature<B>> {
+        self.creatures
+            .iter()
+            .find(|(c, _)| *c == creature)
+            .map(|(_, body)| Creature { world: self, body })
+    }
+
+    /// Removes the creature's bodies and colliders, returning false if it doesn't exist
+    pub fn remove_creature(&mut self, creature: CreatureHandle) -> bool {
+        let index = match self.creatures.iter().position(|(c, _)| *c == creature) {
+            Some(i) => i,
+            None => return false,
+        };
+
+        let (_, body) = self.creatures.swap_remove(index);
+        for part in &body.parts {
+            // removing a link may have already removed its descendants
+            if self.physics.collider_position(part.collider).is_some() {
+                self.physics.remove_body(part.body);
+            }
+        }
+
+        self.objects.retain(|(_, o)| o.creature != Some(creature));
+        true
+    }
+
+    /// Removes the creature and realises the given tree in its place, resting on the terrain
+    /// with the default clearance. Returns None if the creature doesn't exist
+    pub fn replace_creature(
+        &mut self,
+        creature: CreatureHandle,
+        tree: &BodyTree,
+    ) -> Option<CreatureHandle> {
+        let spawn_pos = self
+            .creatures
+            .iter()
+            .find(|(c, _)| *c == creature)
+            .map(|(_, body)| body.spawn_pos)?;
+
+        self.remove_creature(creature);
+        let mut realiser = PhysicalRealiser::new(self);
+        realiser.next_spawn_pos = spawn_pos;
+        realiser.ground_clearance = Some(DEFAULT_GROUND_CLEARANCE);
+        Some(realiser.realise(tree))
+    }
+
+    pub fn set_self_collision(&mut self, self_collision: SelfCollision) {
+        self.config.self_collision = self_collision;
+    }
+
+    fn part_collision_filter(&self, creature: CreatureHandle, depth: usize) -> CollisionFilter {
+        // isolated creatures share level groups, and are kept apart by their codes instead:
+        // two different codes differ in some bit, so each is a member of a group the other
+        // blacklists
+        let (first_level, mut membership, mut blacklist): (usize, Vec<usize>, Vec<usize>) =
+            if self.config.isolate_creatures {
+                let bit = |b: usize| (creature.0 >> b) & 1;
+                let code = |b: usize, value: usize| 1 + 2 * b + value;
+                (
+                    GROUP_LEVELS,
+                    (0..CODE_BITS).map(|b| code(b, bit(b))).collect(),
+                    (0..CODE_BITS).map(|b| code(b, 1 - bit(b))).collect(),
+                )
+            } else {
+                let slot = creature.0 % CREATURE_SLOTS;
+                (1 + slot * GROUPS_PER_CREATURE, Vec::new(), Vec::new())
+            };
+        let level = |depth: usize| first_level + depth % GROUPS_PER_CREATURE;
+
+        membership.push(level(depth));
+        match self.config.self_collision {
+            SelfCollision::Disabled => blacklist.extend((0..GROUPS_PER_CREATURE).map(level)),
+            SelfCollision::NonAdjacent => {
+                // parent and child levels
+                blacklist.extend(&[level(depth + 1), level(depth + GROUPS_PER_CREATURE - 1)])
+            }
+            SelfCollision::Full => {}
+        }
+        CollisionFilter::new(&membership).with_blacklist(&blacklist)
+    }
+
+    fn part_velocity(&self, body: B::Body) -> Velocity {
+        self.physics.body_velocity(body)
+    }
+
+    pub fn ground(&self) -> Option<B::Collider> {
+        self.ground_collider
+    }
+
+    /// Contacts that started or stopped during the last tick. Contacts with colliders that
+    /// have since been removed are skipped.
+    pub fn contact_events(&self) -> Vec<ContactEvent> {
+        self.physics
+            .contact_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                Contact::Started(a, b) => Some(ContactEvent::Started(
+                    self.contactee(a)?,
+                    self.contactee(b)?,
+                )),
+                Contact::Stopped(a, b) => Some(ContactEvent::Stopped(
+                    self.contactee(a)?,
+                    self.contactee(b)?,
+                )),
+            })
+            .collect()
+    }
+
+    fn contactee(&self, collider: B::Collider) -> Option<Contactee> {
+        let (_, object) = self.objects.iter().find(|(ch, _)| *ch == collider)?;
+
+        match object.creature {
+            None => Some(Contactee::Ground),
+            Some(creature) => {
+                let (_, body) = self.creatures.iter().find(|(c, _)| *c == creature)?;
+                let part = body.parts.iter().position(|p| p.collider == collider)?;
+                Some(Contactee::Part { creature, part })
+            }
+        }
+    }
+
+    pub fn set_environment(&mut self, environment: Environment) {
+        match environment {
+            Environment::Land => self.physics.set_gravity(self.config.gravity),
+            // buoyancy cancels out gravity
+            Environment::Water { .. } => self.physics.set_gravity(Vector3::zeros()),
+        }
+        self.environment = environment;
     }
 
     pub fn tick(&mut self) {
+        if let Environment::Water { drag } = self.environment {
+            self.apply_drag(drag);
+        }
         self.physics.step();
+        self.account_energy();
+        if let Some(limits) = self.config.sanity {
+            self.check_sanity(&limits);
+        }
+    }
+
+    /// Adds the work done by each motor this tick to its creature's total, from the impulse it
+    /// applied. Backends that don't report it are charged the most impulse the motor may apply,
+    /// which overestimates for motors that have reached their target speed.
+    fn account_energy(&mut self) {
+        for i in 0..self.creatures.len() {
+            let used: Coord = {
+                let (_, body) = &self.creatures[i];
+                if body.exhausted {
+                    continue;
+                }
+                body.parts
+                    .iter()
+                    .filter_map(|part| {
+                        let max_torque = match part.joint {
+                            def::Joint::Rotational { torque, .. } => torque.get_scaled(),
+                            _ => return None,
+                        };
+                        let parent = &body.parts[part.parent?];
+                        let speed = self.joint_speed(parent.body, part.body);
+                        let impulse = self.physics.motor_impulse(part.body).unwrap_or(max_torque);
+                        Some((impulse * speed).abs())
+                    })
+                    .sum()
+            };
+
+            let exhausted = {
+                let (_, body) = &mut self.creatures[i];
+                body.energy_used += used;
+                self.config
+                    .energy_cap
+                    .map_or(false, |cap| body.energy_used >= cap)
+            };
+            if exhausted {
+                self.disable_motors(i);
+            }
+        }
+    }
+
+    /// Angular speed of a child about its joint axis, relative to its parent
+    fn joint_speed(&self, parent: B::Body, child: B::Body) -> Coord {
+        let axis = self.physics.body_position(child).rotation * Vector3::x();
+        let relative = self.part_velocity(child).angular - self.part_velocity(parent).angular;
+        relative.dot(&axis)
+    }
+
+    fn disable_motors(&mut self, creature_index: usize) {
+        let (_, body) = &mut self.creatures[creature_index];
+        body.exhausted = true;
+        for part in &body.parts {
+            if let def::Joint::Rotational { .. } = part.joint {
+                self.physics.disable_motor(part.body);
+            }
+        }
+    }
+
+    /// Marks creatures that have blown up this tick as invalid
+    fn check_sanity(&mut self, limits: &SanityLimits) {
+        for i in 0..self.creatures.len() {
+            let result = {
+                let (_, body) = &self.creatures[i];
+                if body.invalid.is_some() {
+                    continue;
+                }
+                let creature = Creature { world: self, body };
+                sanity::check(limits, &creature.part_samples(), body.kinetic_energy)
+            };
+
+            let (_, body) = &mut self.creatures[i];
+            match result {
+                Ok(energy) => body.kinetic_energy = energy,
+                Err(reason) => body.invalid = Some(reason),
+            }
+        }
+    }
+
+    /// Pushes every part against its movement through the water, for the next step
+    fn apply_drag(&mut self, coefficient: Coord) {
+        let physics = &mut self.physics;
+        let parts = self
+            .creatures
+            .iter()
+            .flat_map(|(_, body)| body.parts.iter());
+        for part in parts {
+            if physics.collider_position(part.collider).is_none() {
+                continue;
+            }
+
+            let pos = physics.body_position(part.body);
+            let vel = physics.body_velocity(part.body);
+            let (force, torque) = fluid::drag_on_cuboid(
+                coefficient,
+                &pos,
+                &part.half_extents,
+                &vel.linear,
+                &vel.angular,
+            );
+            physics.apply_force(part.body, force, torque);
+        }
     }
 
     fn add_ground(&mut self) {
-        let material = Material::default();
-        let ground_size = 100.0;
-        let ground_shape =
-            ShapeHandle::new(Cuboid::new(Vector3::repeat(ground_size - COLLIDER_MARGIN)));
-        let ground_pos = Isometry3::new(Vector3::y() * -ground_size, zero());
+        let ground_size = self.config.ground_size;
+        let margin = self.config.collider_margin;
+        let ground_shape = ColliderShape::Cuboid(Vector3::repeat(ground_size - margin));
+        let ground_rot = self.terrain.ground_rotation();
+        let ground_pos = terrain::ground_position(ground_size, &ground_rot);
 
+        let ground_body = self.physics.ground();
         let ground = self.physics.add_collider(
-            COLLIDER_MARGIN,
-            ground_shape,
-            BodyHandle::ground(),
-            ground_pos,
-            material,
+            ground_body,
+            &ground_shape,
+            &ground_pos,
+            margin,
+            &CollisionFilter::new(&[GROUP_GROUND]),
         );
 
         let ground_obj = WorldObject::new(
-            ObjectShape::Plane(Point3::new(0.0, 0.0, 0.0), Vector3::y(), ground_size),
+            ObjectShape::Plane(
+                Point3::new(0.0, 0.0, 0.0),
+                ground_rot * Vector3::y(),
+                ground_size,
+            ),
             COLOUR_GROUND,
+            None,
         );
         self.ground_collider = Some(ground);
         self.register_created_object(ground, ground_obj);
+
+        self.add_terrain_blocks();
     }
 
-    pub fn clear(&mut self) {
-        for (ch, _) in &self.objects {
-            let bh = {
-                match self.physics.collider(*ch) {
-                    Some(col) => col.data().body(),
-                    None => continue,
-                }
-            };
-            self.physics.remove_bodies(&[bh]);
+    /// All blocks are a single compound collider
+    fn add_terrain_blocks(&mut self) {
+        self.terrain_blocks = self.terrain.blocks();
+        let blocks = &self.terrain_blocks;
+        if blocks.is_empty() {
+            return;
         }
+
+        let margin = self.config.collider_margin;
+        let shape = ColliderShape::Cuboids(
+            blocks
+                .iter()
+                .map(|b| (b.position, b.inner_half_extents(margin)))
+                .collect(),
+        );
+
+        let ground_body = self.physics.ground();
+        let collider = self.physics.add_collider(
+            ground_body,
+            &shape,
+            &Isometry3::identity(),
+            margin,
+            &CollisionFilter::new(&[GROUP_GROUND]),
+        );
+
+        let object = WorldObject::new(
+            ObjectShape::Blocks(
+                blocks
+                    .iter()
+                    .map(|b| (b.position, b.half_extents))
+                    .collect(),
+            ),
+            COLOUR_TERRAIN,
+            None,
+        );
+        self.register_created_object(collider, object);
+    }
+
+    pub fn clear(&mut self) {
+        self.physics.clear();
         self.ground_collider = None;
         self.objects.clear();
+        self.creatures.clear();
+        self.creature_count = 0;
+        self.generation += 1;
 
         // rather awful
         self.add_ground();
     }
 }
 
-pub struct PhysicalRealiser<'w> {
-    world: &'w mut World,
+pub struct PhysicalRealiser<'w, B: 'w + PhysicsBackend = NPhysicsBackend> {
+    world: &'w mut World<B>,
     pub next_spawn_pos: Vector3<Coord>,
+    /// If set, the height of `next_spawn_pos` is ignored and creatures are spawned with their
+    /// lowest point this far above the terrain. Unset by default, so creatures are dropped
+    /// from `next_spawn_pos`
+    pub ground_clearance: Option<Coord>,
+    /// Where the creature currently being realised is spawned
+    spawn_pos: Vector3<Coord>,
     random: rand::ThreadRng,
+    creature: Option<CreatureHandle>,
+    parts: HashMap<B::Collider, RealisedPart>,
+}
+
+struct RealisedPart {
+    depth: usize,
+    index: usize,
 }
 
-impl<'w> PhysicalRealiser<'w> {
-    pub fn new(world: &'w mut World) -> Self {
+impl<'w, B: PhysicsBackend> PhysicalRealiser<'w, B> {
+    pub fn new(world: &'w mut World<B>) -> Self {
         Self {
             world,
             next_spawn_pos: Vector3::new(0.0, 10.0, 0.0),
+            ground_clearance: None,
+            spawn_pos: zero(),
             random: rand::thread_rng(),
+            creature: None,
+            parts: HashMap::new(),
         }
     }
+
+    /// Realises the tree at `next_spawn_pos` as a new creature
+    pub fn realise(&mut self, tree: &BodyTree) -> CreatureHandle {
+        self.spawn_pos = match self.ground_clearance {
+            Some(clearance) => self.grounded_spawn_pos(tree, clearance),
+            None => self.next_spawn_pos,
+        };
+        self.creature = None;
+        tree.realise(self);
+        self.creature.expect("Tree realised without a root")
+    }
+
+    /// Raises or lowers `next_spawn_pos` so the tree just clears the terrain beneath it
+    fn grounded_spawn_pos(&self, tree: &BodyTree, clearance: Coord) -> Vector3<Coord> {
+        let (x, z) = (self.next_spawn_pos.x, self.next_spawn_pos.z);
+        let offset = Isometry3::new(Vector3::new(x, 0.0, z), zero());
+        let (terrain, blocks) = (&self.world.terrain, &self.world.terrain_blocks);
+
+        // how far each part's footprint must be raised to be above the terrain
+        let lift = placement::layout(tree)
+            .iter()
+            .map(|part| part.aabb(&offset))
+            .map(|aabb| {
+                let (mins, maxs) = (aabb.mins(), aabb.maxs());
+                let centre = aabb.center();
+                let samples = [
+                    (centre.x, centre.z),
+                    (mins.x, mins.z),
+                    (mins.x, maxs.z),
+                    (maxs.x, mins.z),
+                    (maxs.x, maxs.z),
+                ];
+                samples
+                    .iter()
+                    .map(|(x, z)| terrain.height_among(blocks, *x, *z))
+                    .fold(::std::f64::MIN, Coord::max)
+                    - mins.y
+            })
+            .fold(::std::f64::MIN, Coord::max);
+
+        Vector3::new(x, lift + clearance, z)
+    }
 }
 
 fn position_on_face(
@@ -193,10 +746,11 @@ fn position_on_face(
     }
 }
 
-fn shape_from_def(
+/// Returns (offset from the parent, rotation as a scaled axis)
+pub(crate) fn shape_from_def(
     definition: &def::ShapeDefinition,
     parent_shape: &ObjectShape,
-) -> (ShapeHandle<Coord>, Vector3<Coord>, Vector3<Coord>) {
+) -> (Vector3<Coord>, Vector3<Coord>) {
     match definition {
         def::ShapeDefinition::Cuboid { dims, pos, rot } => {
             let (w, h, d) = dims.components_scaled();
@@ -213,28 +767,22 @@ fn shape_from_def(
             };
 
             let offset = match parent_shape {
-                ObjectShape::Plane(_, _, _) => Vector3::identity(),
-                ObjectShape::Cuboid(parent_dims) => {
-                    use body_tree::body::def::RangedParam; // ooer
-
-                    const FACE_COUNT: f64 = 6.0;
-                    let face = (face_idx.get() * FACE_COUNT).min(FACE_COUNT - 1.0) as u32;
-                    position_on_face(
-                        face,
-                        (face_1.get_scaled(), face_2.get_scaled()),
-                        &my_size,
-                        &parent_dims,
-                    )
-                }
+                ObjectShape::Plane(_, _, _) | ObjectShape::Blocks(_) => Vector3::identity(),
+                ObjectShape::Cuboid(parent_dims) => position_on_face(
+                    face_idx.face(),
+                    (face_1.get_scaled(), face_2.get_scaled()),
+                    &my_size,
+                    &parent_dims,
+                ),
             };
 
-            (ShapeHandle::new(cuboid), offset, Vector3::new(rx, ry, rz))
+            (offset, Vector3::new(rx, ry, rz))
         }
     }
 }
 
-impl<'w> TreeRealiser for PhysicalRealiser<'w> {
-    type RealisedHandle = (ColliderHandle, BodyHandle);
+impl<'w, B: PhysicsBackend> TreeRealiser for PhysicalRealiser<'w, B> {
+    type RealisedHandle = (B::Collider, B::Body);
 
     fn new_shape(
         &mut self,
@@ -242,39 +790,23 @@ impl<'w> TreeRealiser for PhysicalRealiser<'w> {
         parent: Self::RealisedHandle,
         parent_joint: &def::Joint,
     ) -> Self::RealisedHandle {
-        // helper
-        fn add_link<J: Joint<Coord>>(
-            world: &mut World,
-            parent: BodyHandle,
-            offset: Isometry3<Coord>,
-            joint: J,
-            shape: &ShapeHandle<Coord>,
-        ) -> BodyHandle {
-            let inertia = shape.inertia(1.0);
-            let com = shape.center_of_mass();
-            world.physics.add_multibody_link(
-                parent,
-                joint,
-                offset.translation.vector,
-                zero(),
-                inertia,
-                com,
-            )
-        }
-
         let (parent_ch, parent_body) = parent;
+        let (depth, parent_index) = match parent_joint {
+            def::Joint::Ground => {
+                self.creature = Some(self.world.new_creature(self.spawn_pos));
+                (0, None)
+            }
+            _ => {
+                let parent = &self.parts[&parent_ch];
+                (parent.depth + 1, Some(parent.index))
+            }
+        };
+
         let parent_shape = self.world.shape(parent_ch).expect("Parent has no collider");
 
         // get parameters from shape definition
-        let (body_shape, rel_pos, rotation) = shape_from_def(&shape_def, &parent_shape);
-
-        // get parent global position
-        let parent_pos = {
-            match self.world.physics.multibody_link(parent_body) {
-                Some(link) => link.position(),
-                None => Isometry3::new(self.next_spawn_pos, rotation), // spawn position of full entity
-            }
-        };
+        let (rel_pos, rotation) = shape_from_def(&shape_def, parent_shape);
+        let half_extents = half_extents(shape_def);
 
         // parse parameters
         let joint_params = {
@@ -283,43 +815,52 @@ impl<'w> TreeRealiser for PhysicalRealiser<'w> {
             shift
         };
 
-        let link = match parent_joint {
-            def::Joint::Ground => add_link(
-                self.world,
-                parent_body,
-                Isometry3::identity(),
-                FreeJoint::new(parent_pos),
-                &body_shape,
-            ),
-            def::Joint::Fixed => add_link(
-                self.world,
-                parent_body,
-                Isometry3::identity(),
-                FixedJoint::new(joint_params),
-                &body_shape,
-            ),
-            def::Joint::Rotational { torque, max_speed } => {
-                let mut rev = RevoluteJoint::new(Vector3::x_axis(), 0.0);
-                rev.enable_angular_motor();
-                rev.set_desired_angular_motor_velocity(max_speed.get_scaled());
-                rev.set_max_angular_motor_torque(torque.get_scaled());
-                rev.disable_min_angle();
-                rev.disable_max_angle();
-
-                add_link(self.world, parent_body, joint_params, rev, &body_shape)
-            }
+        let joint = match parent_joint {
+            // spawn position of full entity
+            def::Joint::Ground => LinkJoint::Free(Isometry3::new(self.spawn_pos, rotation)),
+            def::Joint::Fixed => LinkJoint::Fixed(joint_params),
+            def::Joint::Rotational { torque, max_speed } => LinkJoint::Revolute {
+                offset: joint_params.translation.vector,
+                motor_speed: max_speed.get_scaled(),
+                max_torque: torque.get_scaled(),
+            },
         };
+        let link = self
+            .world
+            .physics
+            .add_link(parent_body, &joint, &half_extents);
 
+        let creature = self.creature.expect("Creature root not realised");
+        let filter = self.world.part_collision_filter(creature, depth);
         let collider = self.world.physics.add_collider(
-            COLLIDER_MARGIN,
-            body_shape,
             link,
-            Isometry3::identity(),
-            Material::default(),
+            &ColliderShape::Cuboid(half_extents),
+            &Isometry3::identity(),
+            self.world.config.collider_margin,
+            &filter,
         );
 
-        self.world
-            .register_object(collider, shape_def, Colour::random(&mut self.random));
+        self.world.register_object(
+            collider,
+            shape_def,
+            Colour::random(&mut self.random),
+            Some(creature),
+        );
+
+        // unit density
+        let mass = 8.0 * half_extents.x * half_extents.y * half_extents.z;
+        let index = self.world.register_part(
+            creature,
+            CreaturePart {
+                collider,
+                body: link,
+                parent: parent_index,
+                joint: *parent_joint,
+                mass,
+                half_extents,
+            },
+        );
+        self.parts.insert(collider, RealisedPart { depth, index });
 
         (collider, link)
     }
@@ -328,20 +869,153 @@ impl<'w> TreeRealiser for PhysicalRealiser<'w> {
         (
             (
                 self.world.ground_collider.expect("Ground required"),
-                BodyHandle::ground(),
+                self.world.physics.ground(),
             ),
             def::Joint::Ground,
         )
     }
 }
 
+impl<'w, B: PhysicsBackend> Creature<'w, B> {
+    fn part_positions<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (&'a CreaturePart<B>, Isometry3<Coord>)> + 'a {
+        self.body.parts.iter().filter_map(move |part| {
+            self.world
+                .physics
+                .collider_position(part.collider)
+                .map(|pos| (part, pos))
+        })
+    }
+
+    fn part_samples(&self) -> Vec<PartSample> {
+        self.part_positions()
+            .map(|(part, position)| {
+                let vel = self.world.part_velocity(part.body);
+                PartSample {
+                    position,
+                    linear: vel.linear,
+                    angular: vel.angular,
+                    mass: part.mass,
+                }
+            })
+            .collect()
+    }
+
+    /// Why the simulation of this creature blew up, if it has. Once set it's never cleared.
+    pub fn invalid(&self) -> Option<Invalidity> {
+        self.body.invalid
+    }
+
+    /// Joules spent by the creature's motors so far
+    pub fn energy_used(&self) -> Coord {
+        self.body.energy_used
+    }
+
+    /// Whether the creature has hit the energy cap, and its motors switched off
+    pub fn exhausted(&self) -> bool {
+        self.body.exhausted
+    }
+
+    pub fn part_count(&self) -> usize {
+        self.body.parts.len()
+    }
+
+    /// Position of the root part, None if it has been removed
+    pub fn root_position(&self) -> Option<Isometry3<Coord>> {
+        let root = self.body.parts.first()?;
+        self.world.physics.collider_position(root.collider)
+    }
+
+    pub fn mass(&self) -> Coord {
+        self.body.parts.iter().map(|p| p.mass).sum()
+    }
+
+    pub fn centre_of_mass(&self) -> Point3<Coord> {
+        let (weighted, mass) =
+            self.part_positions()
+                .fold((Vector3::zeros(), 0.0), |(weighted, mass), (part, pos)| {
+                    (
+                        weighted + pos.translation.vector * part.mass,
+                        mass + part.mass,
+                    )
+                });
+        if mass > 0.0 {
+            Point3::from_coordinates(weighted / mass)
+        } else {
+            Point3::origin()
+        }
+    }
+
+    /// Mass weighted mean of the velocity of each part
+    pub fn linear_velocity(&self) -> Vector3<Coord> {
+        let (weighted, mass) =
+            self.body
+                .parts
+                .iter()
+                .fold((Vector3::zeros(), 0.0), |(weighted, mass), part| {
+                    let vel = self.world.part_velocity(part.body);
+                    (weighted + vel.linear * part.mass, mass + part.mass)
+                });
+        if mass > 0.0 {
+            weighted / mass
+        } else {
+            Vector3::zeros()
+        }
+    }
+
+    /// Angular velocity of the root part only, the rest of the creature may be spinning
+    /// differently about its joints
+    pub fn angular_velocity(&self) -> Vector3<Coord> {
+        self.body.parts.first().map_or(Vector3::zeros(), |root| {
+            self.world.part_velocity(root.body).angular
+        })
+    }
+
+    pub fn aabb(&self) -> Option<AABB<Coord>> {
+        self.part_positions()
+            .map(|(part, pos)| -> AABB<Coord> {
+                Cuboid::new(part.half_extents).bounding_volume(&pos)
+            })
+            .fold(None, |acc: Option<AABB<Coord>>, aabb| match acc {
+                Some(acc) => Some(acc.merged(&aabb)),
+                None => Some(aabb),
+            })
+    }
+
+    /// Height of the highest point of any part
+    pub fn height(&self) -> Coord {
+        self.aabb().map_or(0.0, |aabb| aabb.maxs().y)
+    }
+
+    /// Current angle of each rotational joint, as (part index, angle)
+    pub fn joint_angles(&self) -> Vec<(usize, Coord)> {
+        self.body
+            .parts
+            .iter()
+            .enumerate()
+            .filter(|(_, part)| match part.joint {
+                def::Joint::Rotational { .. } => true,
+                _ => false,
+            })
+            .filter_map(|(i, part)| Some((i, self.world.physics.joint_angle(part.body)?)))
+            .collect()
+    }
+}
+
+pub(crate) fn half_extents(definition: &def::ShapeDefinition) -> Vector3<Coord> {
+    match definition {
+        def::ShapeDefinition::Cuboid { dims, .. } => {
+            let (w, h, d) = dims.components_scaled();
+            Vector3::new(w, h, d)
+        }
+    }
+}
+
 impl ObjectShape {
     fn from_def(def: &def::ShapeDefinition) -> Self {
         match def {
-            def::ShapeDefinition::Cuboid { dims, .. } => {
-                let (w, h, d) = dims.components_scaled();
-                ObjectShape::Cuboid(Vector3::new(w, h, d))
-            }
+            def::ShapeDefinition::Cuboid { .. } => ObjectShape::Cuboid(half_extents(def)),
         }
     }
 }
@@ -355,3 +1029,406 @@ impl Colour {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body_tree::body::params::{MaxSpeed, Torque};
+
+    /// A block with another hinged on top of it
+    fn hinged() -> BodyTree {
+        let mut tree = BodyTree::with_root(def::new_cuboid(
+            (0.5, 0.5, 0.5),
+            (0.0, 0.0, 0.0),
+            (0.0, 0.0, 0.0),
+        ));
+        let root = tree.root();
+        tree.add_child(
+            root,
+            // the middle of the top face
+            def::new_cuboid((0.3, 0.3, 0.3), (0.25, 0.0, 0.0), (0.0, 0.0, 0.0)),
+            def::Joint::Rotational {
+                torque: Torque::new(1.0),
+                max_speed: MaxSpeed::new(1.0),
+            },
+        );
+        tree
+    }
+
+    fn realise_hinged<B: PhysicsBackend>(config: WorldConfig) -> (World<B>, CreatureHandle) {
+        let mut world = World::new(config);
+        world.clear();
+        let creature = {
+            let mut r = PhysicalRealiser::new(&mut world);
+            r.ground_clearance = Some(DEFAULT_GROUND_CLEARANCE);
+            r.realise(&hinged())
+        };
+        (world, creature)
+    }
+
+    fn world(isolate: bool, self_collision: SelfCollision) -> World {
+        World::new(WorldConfig {
+            isolate_creatures: isolate,
+            self_collision,
+            ..WorldConfig::default()
+        })
+    }
+
+    fn interacts(world: &World, a: (usize, usize), b: (usize, usize)) -> bool {
+        let a = world.part_collision_filter(CreatureHandle(a.0), a.1);
+        let b = world.part_collision_filter(CreatureHandle(b.0), b.1);
+        a.can_interact_with(&b)
+    }
+
+    #[test]
+    fn isolation() {
+        let isolated = world(true, SelfCollision::Full);
+        let ground = CollisionFilter::new(&[GROUP_GROUND]);
+
+        // well past the 7 slots and 29 groups there are
+        for a in 0..64 {
+            for depth in 0..GROUPS_PER_CREATURE {
+                let filter = isolated.part_collision_filter(CreatureHandle(a), depth);
+                assert!(filter.can_interact_with(&ground));
+                assert!(interacts(&isolated, (a, depth), (a, 0)));
+                for b in (0..64).filter(|b| *b != a) {
+                    assert!(!interacts(&isolated, (a, depth), (b, depth)), "{} {}", a, b);
+                    assert!(!interacts(&isolated, (a, depth), (b, 0)), "{} {}", a, b);
+                }
+            }
+        }
+
+        let shared = world(false, SelfCollision::Full);
+        assert!(interacts(&shared, (0, 0), (1, 0)));
+        assert!(interacts(&shared, (0, 0), (40, 1)));
+    }
+
+    #[test]
+    fn self_collision() {
+        for isolate in &[true, false] {
+            let disabled = world(*isolate, SelfCollision::Disabled);
+            let non_adjacent = world(*isolate, SelfCollision::NonAdjacent);
+            let full = world(*isolate, SelfCollision::Full);
+
+            for depth in 0..6 {
+                assert!(!interacts(&disabled, (3, depth), (3, 0)));
+                assert!(interacts(&full, (3, depth), (3, 0)));
+            }
+
+            // levels next to each other are kept apart, modulo the number of level groups
+            let collides = |a, b| interacts(&non_adjacent, (3, a), (3, b));
+            assert!(collides(0, 0));
+            assert!(!collides(0, 1));
+            assert!(!collides(2, 1));
+            assert!(collides(0, 2));
+            assert!(!collides(0, 3));
+            assert!(collides(1, 5));
+            assert!(!collides(1, 6));
+        }
+
+        // without isolation, the policy doesn't apply between different creatures
+        let disabled = world(false, SelfCollision::Disabled);
+        assert!(interacts(&disabled, (0, 0), (1, 0)));
+        assert!(interacts(&disabled, (0, 0), (1, 1)));
+    }
+
+    #[test]
+    fn energy() {
+        let (mut world, creature) = realise_hinged::<NPhysicsBackend>(WorldConfig::default());
+        let mut spent = Vec::new();
+        for _ in 0..3 {
+            for _ in 0..20 {
+                world.tick();
+            }
+            spent.push(world.creature(creature).unwrap().energy_used());
+        }
+        assert!(0.0 < spent[0] && spent[0] < spent[1] && spent[1] < spent[2]);
+
+        // never more than the joint's max torque
+        let impulse = world
+            .physics
+            .motor_impulse(world.creatures[0].1.parts[1].body);
+        let max = 5.0 * world.config.timestep;
+        assert!(impulse.map_or(false, |i| i != 0.0 && i.abs() <= max + 1e-9));
+    }
+
+    #[test]
+    fn energy_cap() {
+        let (mut world, creature) = realise_hinged::<NPhysicsBackend>(WorldConfig {
+            energy_cap: Some(0.05),
+            ..WorldConfig::default()
+        });
+        for _ in 0..120 {
+            world.tick();
+        }
+
+        let (exhausted, used) = {
+            let c = world.creature(creature).unwrap();
+            (c.exhausted(), c.energy_used())
+        };
+        assert!(exhausted);
+        assert!(used >= 0.05);
+        let child = world.creatures[0].1.parts[1].body;
+        assert_eq!(world.physics.motor_impulse(child), Some(0.0));
+
+        // nothing more is spent once the motors are off
+        for _ in 0..30 {
+            world.tick();
+        }
+        assert_eq!(world.creature(creature).unwrap().energy_used(), used);
+    }
+
+    fn creature_colliders(
+        world: &World,
+        creature: CreatureHandle,
+    ) -> Vec<<NPhysicsBackend as PhysicsBackend>::Collider> {
+        world
+            .objects
+            .iter()
+            .filter(|(_, o)| o.creature == Some(creature))
+            .map(|(ch, _)| *ch)
+            .collect()
+    }
+
+    #[test]
+    fn remove_creature() {
+        let mut world: World = World::default();
+        world.clear();
+        let (a, b) = {
+            let mut r = PhysicalRealiser::new(&mut world);
+            let a = r.realise(&hinged());
+            r.next_spawn_pos = Vector3::new(10.0, 0.0, 0.0);
+            (a, r.realise(&hinged()))
+        };
+        let removed = creature_colliders(&world, a);
+        let kept = creature_colliders(&world, b);
+        assert_eq!((removed.len(), kept.len()), (2, 2));
+        let kept_position = world.creature(b).unwrap().root_position();
+
+        assert!(world.remove_creature(a));
+        assert!(!world.remove_creature(a));
+        assert!(world.creature(a).is_none());
+        assert!(creature_colliders(&world, a).is_empty());
+        for ch in removed {
+            assert!(world.physics.collider_position(ch).is_none());
+        }
+
+        // the other creature is untouched
+        assert_eq!(creature_colliders(&world, b), kept);
+        for ch in kept {
+            assert!(world.physics.collider_position(ch).is_some());
+        }
+        let other = world.creature(b).unwrap();
+        assert_eq!(other.part_count(), 2);
+        assert_eq!(other.root_position(), kept_position);
+    }
+
+    #[test]
+    fn replace_creature() {
+        let (mut world, old) = realise_hinged::<NPhysicsBackend>(WorldConfig::default());
+        let tree = BodyTree::with_root(def::new_cuboid(
+            (0.2, 0.2, 0.2),
+            (0.0, 0.0, 0.0),
+            (0.0, 0.0, 0.0),
+        ));
+
+        let new = world.replace_creature(old, &tree).unwrap();
+        assert_ne!(new, old);
+        assert!(world.creature(old).is_none());
+        assert!(world.replace_creature(old, &tree).is_none());
+        assert!(creature_colliders(&world, old).is_empty());
+
+        let creature = world.creature(new).unwrap();
+        assert_eq!(creature.part_count(), 1);
+        assert!(creature.root_position().is_some());
+        assert_eq!(creature_colliders(&world, new).len(), 1);
+    }
+
+    #[test]
+    fn mass_and_centre() {
+        let mut tree = BodyTree::with_root(def::new_cuboid(
+            (0.5, 0.5, 0.5),
+            (0.0, 0.0, 0.0),
+            (0.0, 0.0, 0.0),
+        ));
+        let root = tree.root();
+        // centred on the top face
+        let top = def::new_cuboid((0.3, 0.3, 0.3), (0.25, 0.5, 0.5), (0.0, 0.0, 0.0));
+        let hinge = def::Joint::Rotational {
+            torque: Torque::new(1.0),
+            max_speed: MaxSpeed::new(1.0),
+        };
+        tree.add_child(root, top, hinge);
+
+        let mut world: World = World::default();
+        world.clear();
+        let creature = {
+            let mut r = PhysicalRealiser::new(&mut world);
+            r.next_spawn_pos = Vector3::new(1.0, 2.0, 3.0);
+            r.realise(&tree)
+        };
+        let creature = world.creature(creature).unwrap();
+
+        // half extents of 2.05 and 1.27 at unit density
+        let (root_mass, top_mass) = (8.0 * 2.05 * 2.05 * 2.05, 8.0 * 1.27 * 1.27 * 1.27);
+        assert!((creature.mass() - (root_mass + top_mass)).abs() < 1e-9);
+
+        // the top is raised by 2.05 / 2 - 1.27 / 2 above the root
+        let root_pos = creature.root_position().unwrap().translation.vector;
+        let expected = root_pos + Vector3::y() * 0.39 * top_mass / (root_mass + top_mass);
+        let centre = creature.centre_of_mass().coords;
+        assert!(
+            (centre - expected).norm() < 1e-9,
+            "{} vs {}",
+            centre,
+            expected
+        );
+    }
+
+    #[test]
+    fn water() {
+        let mut world: World = World::default();
+        world.clear();
+        world.set_environment(Environment::Water { drag: 2.0 });
+        let creature = {
+            let mut r = PhysicalRealiser::new(&mut world);
+            // well clear of the ground, and without a motor stirring the water
+            r.next_spawn_pos = Vector3::new(0.0, 20.0, 0.0);
+            r.realise(&BodyTree::with_root(def::new_cuboid(
+                (0.5, 0.5, 0.5),
+                (0.0, 0.0, 0.0),
+                (0.0, 0.0, 0.0),
+            )))
+        };
+
+        // a sideways shove of 2m/s
+        let dt = world.config.timestep;
+        for part in &world.creatures[0].1.parts {
+            let push = Vector3::x() * 2.0 * part.mass / dt;
+            world.physics.apply_force(part.body, push, zero());
+        }
+        // nphysics only reports the new velocity of a link after the step following the push
+        world.tick();
+        world.tick();
+
+        let state = |world: &World| {
+            let c = world.creature(creature).unwrap();
+            (c.linear_velocity().x, c.centre_of_mass().y)
+        };
+        let (mut speed, height) = state(&world);
+        assert!(speed > 1.5, "{}", speed);
+
+        for _ in 0..4 {
+            for _ in 0..30 {
+                world.tick();
+            }
+            let (slower, y) = state(&world);
+            assert!(slower < speed, "{} then {}", speed, slower);
+            // no gravity to sink under
+            assert!((y - height).abs() < 0.01, "{} then {}", height, y);
+            speed = slower;
+        }
+        assert!(speed < 1.0, "{}", speed);
+    }
+
+    #[cfg(feature = "rapier")]
+    #[test]
+    fn backends_agree_on_joint_angle() {
+        use rapier_backend::RapierBackend;
+
+        // floating, so only the motor moves the hinge
+        let config = || WorldConfig {
+            gravity: zero(),
+            ..WorldConfig::default()
+        };
+        let (mut nphysics, a) = realise_hinged::<NPhysicsBackend>(config());
+        let (mut rapier, b) = realise_hinged::<RapierBackend>(config());
+
+        for step in 0..120 {
+            nphysics.tick();
+            rapier.tick();
+            let expected = nphysics.creature(a).unwrap().joint_angles()[0].1;
+            let actual = rapier.creature(b).unwrap().joint_angles()[0].1;
+            assert!(
+                (expected - actual).abs() < 0.05 + 0.1 * expected.abs(),
+                "step {}: {} vs {}",
+                step,
+                expected,
+                actual
+            );
+        }
+        assert!(nphysics.creature(a).unwrap().joint_angles()[0].1.abs() > 0.1);
+    }
+
+    #[test]
+    fn contacts() {
+        let mut world: World = World::default();
+        world.clear();
+        let small = || def::new_cuboid((0.1, 0.1, 0.1), (0.0, 0.0, 0.0), (0.0, 0.0, 0.0));
+        let mut tower = BodyTree::with_root(small());
+        let root = tower.root();
+        // on the top face, flat enough to stay clear of the ground as a part the same size as
+        // its parent is placed right on top of it
+        let top = def::new_cuboid((0.1, 0.02, 0.1), (0.25, 0.0, 0.0), (0.0, 0.0, 0.0));
+        tower.add_child(root, top, def::Joint::Fixed);
+
+        let (block, tower) = {
+            let mut r = PhysicalRealiser::new(&mut world);
+            r.next_spawn_pos = Vector3::new(0.0, 2.0, 0.0);
+            let block = r.realise(&BodyTree::with_root(small()));
+            r.next_spawn_pos = Vector3::new(10.0, 2.0, 0.0);
+            (block, r.realise(&tower))
+        };
+
+        let mut events = Vec::new();
+        for _ in 0..120 {
+            world.tick();
+            events.extend(world.contact_events());
+        }
+
+        let on_ground = |a: &Contactee, b: &Contactee, part: Contactee| {
+            (*a, *b) == (Contactee::Ground, part) || (*a, *b) == (part, Contactee::Ground)
+        };
+        let started = |events: &[ContactEvent], part| {
+            events.iter().any(|e| match e {
+                ContactEvent::Started(a, b) => on_ground(a, b, part),
+                _ => false,
+            })
+        };
+        let stopped = |events: &[ContactEvent], part| {
+            events.iter().any(|e| match e {
+                ContactEvent::Stopped(a, b) => on_ground(a, b, part),
+                _ => false,
+            })
+        };
+
+        let block_part = Contactee::Part {
+            creature: block,
+            part: 0,
+        };
+        let tower_root = Contactee::Part {
+            creature: tower,
+            part: 0,
+        };
+        let tower_top = Contactee::Part {
+            creature: tower,
+            part: 1,
+        };
+        assert!(started(&events, block_part));
+        assert!(started(&events, tower_root));
+        // the top part never reaches the ground
+        assert!(!started(&events, tower_top));
+
+        // lift the block back off the ground
+        let body = world.creatures[0].1.parts[0].body;
+        let lift = Vector3::y() * 20.0 * world.creatures[0].1.parts[0].mass;
+        events.clear();
+        for _ in 0..60 {
+            world.physics.apply_force(body, lift, zero());
+            world.tick();
+            events.extend(world.contact_events());
+        }
+        assert!(stopped(&events, block_part));
+        assert!(!stopped(&events, tower_root));
+    }
+}
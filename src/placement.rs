@@ -0,0 +1,267 @@
+//! Checks that a body tree's parts don't start out inside each other.
+//!
+//! Parts are laid out kinematically, exactly as the realiser places them, but without
+//! touching a physics world. Parts that are joined always touch, so only the rest are checked.
+
+use nalgebra::{zero, Isometry3, Translation3, UnitQuaternion, Vector3};
+use ncollide3d::bounding_volume::{HasBoundingVolume, AABB};
+use ncollide3d::query;
+use ncollide3d::shape::Cuboid;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use body_tree::body::{def, params};
+use body_tree::tree::{BodyTree, EdgeRef, NodeIndex};
+use body_tree::Coord;
+use physics::{self, ObjectShape};
+
+/// Parts can sink into each other by this much before it counts as overlapping, so that
+/// neighbours resting flush against each other are fine
+const OVERLAP_TOLERANCE: Coord = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacementError {
+    /// The part overlaps `other` on every face of its parent
+    Overlap { part: NodeIndex, other: NodeIndex },
+}
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlacementError::Overlap { part, other } => write!(
+                f,
+                "part {} overlaps part {} on every face of its parent",
+                part.index(),
+                other.index()
+            ),
+        }
+    }
+}
+
+impl Error for PlacementError {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlacedPart {
+    pub node: NodeIndex,
+    /// Index of the parent in the layout, None for the root
+    pub parent: Option<usize>,
+    /// Relative to the spawn position
+    pub position: Isometry3<Coord>,
+    pub half_extents: Vector3<Coord>,
+}
+
+impl PlacedPart {
+    /// Bounding box once the spawn position is applied
+    pub fn aabb(&self, spawn: &Isometry3<Coord>) -> AABB<Coord> {
+        Cuboid::new(self.half_extents).bounding_volume(&(spawn * self.position))
+    }
+}
+
+/// Every node with its parent and joint, in realisation order
+fn realisation_order(tree: &BodyTree) -> Vec<(NodeIndex, Option<NodeIndex>, def::Joint)> {
+    fn visit(
+        tree: &BodyTree,
+        node: NodeIndex,
+        parent: Option<NodeIndex>,
+        joint: def::Joint,
+        order: &mut Vec<(NodeIndex, Option<NodeIndex>, def::Joint)>,
+    ) {
+        order.push((node, parent, joint));
+        for edge in tree.get_children(node) {
+            visit(tree, edge.source(), Some(node), *edge.weight(), order);
+        }
+    }
+
+    let mut order = Vec::with_capacity(tree.node_count());
+    visit(tree, tree.root(), None, def::Joint::Ground, &mut order);
+    order
+}
+
+fn place(
+    shape_def: &def::ShapeDefinition,
+    node: NodeIndex,
+    parent: Option<(usize, &PlacedPart)>,
+    joint: &def::Joint,
+) -> PlacedPart {
+    let half_extents = physics::half_extents(shape_def);
+    let (parent_index, position) = match parent {
+        None => {
+            let (_, rotation) =
+                physics::shape_from_def(shape_def, &ObjectShape::Cuboid(Vector3::repeat(1.0)));
+            (None, Isometry3::new(zero(), rotation))
+        }
+        Some((index, parent)) => {
+            let parent_shape = ObjectShape::Cuboid(parent.half_extents);
+            let (offset, rotation) = physics::shape_from_def(shape_def, &parent_shape);
+            let mut shift = Isometry3::new(offset, zero());
+            shift.append_rotation_mut(&UnitQuaternion::new(rotation));
+            let relative = match joint {
+                // revolute joints start at angle 0, so only the shifted origin is kept
+                def::Joint::Rotational { .. } => Isometry3::from_parts(
+                    Translation3::from_vector(shift.translation.vector),
+                    UnitQuaternion::identity(),
+                ),
+                _ => shift,
+            };
+            (Some(index), parent.position * relative)
+        }
+    };
+
+    PlacedPart {
+        node,
+        parent: parent_index,
+        position,
+        half_extents,
+    }
+}
+
+fn overlapping(a: &PlacedPart, b: &PlacedPart) -> bool {
+    let a_shape = Cuboid::new(a.half_extents);
+    let b_shape = Cuboid::new(b.half_extents);
+    query::contact(&a.position, &a_shape, &b.position, &b_shape, 0.0)
+        .map_or(false, |contact| contact.depth > OVERLAP_TOLERANCE)
+}
+
+/// The first part already laid out that overlaps this one, other than its parent
+fn first_overlap(part: &PlacedPart, layout: &[PlacedPart]) -> Option<NodeIndex> {
+    layout
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| Some(*i) != part.parent)
+        .find(|(_, other)| overlapping(part, other))
+        .map(|(_, other)| other.node)
+}
+
+/// Positions of every part relative to the spawn position, in realisation order
+pub fn layout(tree: &BodyTree) -> Vec<PlacedPart> {
+    let mut layout: Vec<PlacedPart> = Vec::with_capacity(tree.node_count());
+    let mut indices = HashMap::new();
+    for (node, parent, joint) in realisation_order(tree) {
+        let part = {
+            let parent = parent.map(|p| (indices[&p], &layout[indices[&p]]));
+            place(tree.shape(node), node, parent, &joint)
+        };
+        indices.insert(node, layout.len());
+        layout.push(part);
+    }
+    layout
+}
+
+/// Pairs of parts that overlap but aren't joined to each other
+pub fn overlaps(tree: &BodyTree) -> Vec<(NodeIndex, NodeIndex)> {
+    let layout = layout(tree);
+    let mut pairs = Vec::new();
+    for (i, a) in layout.iter().enumerate() {
+        for (j, b) in layout.iter().enumerate().skip(i + 1) {
+            let joined = a.parent == Some(j) || b.parent == Some(i);
+            if !joined && overlapping(a, b) {
+                pairs.push((a.node, b.node));
+            }
+        }
+    }
+    pairs
+}
+
+/// Moves parts that overlap one realised before them onto another face of their parent,
+/// returning how many were moved. Fails if a part overlaps something on every face, in which
+/// case the tree is left partially repaired.
+pub fn repair(tree: &mut BodyTree) -> Result<usize, PlacementError> {
+    let mut layout: Vec<PlacedPart> = Vec::with_capacity(tree.node_count());
+    let mut indices = HashMap::new();
+    let mut moved = 0;
+
+    for (node, parent, joint) in realisation_order(tree) {
+        let parent = parent.map(|p| indices[&p]);
+        let place_on = |tree: &BodyTree, layout: &[PlacedPart]| {
+            place(
+                tree.shape(node),
+                node,
+                parent.map(|p| (p, &layout[p])),
+                &joint,
+            )
+        };
+
+        let mut part = place_on(tree, &layout);
+        if let Some(other) = first_overlap(&part, &layout) {
+            let original = face(tree.shape(node));
+            let mut fixed = None;
+            for i in 1..params::FACE_COUNT {
+                set_face(tree.shape_mut(node), (original + i) % params::FACE_COUNT);
+                let candidate = place_on(tree, &layout);
+                if first_overlap(&candidate, &layout).is_none() {
+                    fixed = Some(candidate);
+                    break;
+                }
+            }
+
+            part = match fixed {
+                Some(part) => part,
+                None => {
+                    set_face(tree.shape_mut(node), original);
+                    return Err(PlacementError::Overlap { part: node, other });
+                }
+            };
+            moved += 1;
+        }
+
+        indices.insert(node, layout.len());
+        layout.push(part);
+    }
+
+    Ok(moved)
+}
+
+fn face(shape_def: &def::ShapeDefinition) -> u32 {
+    match shape_def {
+        def::ShapeDefinition::Cuboid { pos, .. } => pos.0.face(),
+    }
+}
+
+fn set_face(shape_def: &mut def::ShapeDefinition, face: u32) {
+    match shape_def {
+        def::ShapeDefinition::Cuboid { pos, .. } => pos.0.set_face(face),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big() -> def::ShapeDefinition {
+        def::new_cuboid((1.0, 1.0, 1.0), (0.0, 0.5, 0.5), (0.0, 0.0, 0.0))
+    }
+
+    /// Small cube in the centre of the given face of its parent
+    fn small(face: f64) -> def::ShapeDefinition {
+        def::new_cuboid((0.0, 0.0, 0.0), (face, 0.5, 0.5), (0.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn repairs_siblings() {
+        // two identical children on the same face
+        let mut tree = BodyTree::with_root(big());
+        let root = tree.root();
+        let a = tree.add_child(root, small(0.5), def::Joint::Fixed);
+        let b = tree.add_child(root, small(0.5), def::Joint::Fixed);
+        assert_eq!(overlaps(&tree).len(), 1);
+
+        assert_eq!(repair(&mut tree), Ok(1));
+        assert!(overlaps(&tree).is_empty());
+        assert_ne!(face(tree.shape(a)), face(tree.shape(b)));
+    }
+
+    #[test]
+    fn rejects_crowded() {
+        let mut tree = BodyTree::with_root(big());
+        let root = tree.root();
+        for _ in 0..params::FACE_COUNT + 1 {
+            tree.add_child(root, small(0.0), def::Joint::Fixed);
+        }
+
+        match repair(&mut tree) {
+            Err(PlacementError::Overlap { .. }) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
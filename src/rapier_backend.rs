@@ -0,0 +1,436 @@
+//! `PhysicsBackend` on Rapier, enabled with the `rapier` feature.
+//!
+//! Rapier uses a newer nalgebra than the rest of the crate, so everything crossing the
+//! boundary is converted component by component.
+
+use nalgebra::{Isometry3, Quaternion, Translation3, Unit, UnitQuaternion, Vector3};
+use rapier3d_f64::crossbeam::channel::{unbounded, Receiver};
+use rapier3d_f64::math::{Isometry, Real, Vector};
+use rapier3d_f64::pipeline::ChannelEventCollector;
+use rapier3d_f64::prelude::{
+    ActiveEvents, ActiveHooks, BroadPhase, CCDSolver, ColliderBuilder, ColliderHandle, ColliderSet,
+    CollisionEvent, ContactForceEvent, FixedJointBuilder, GenericJoint, Group, ImpulseJointSet,
+    IntegrationParameters, InteractionGroups, IslandManager, JointAxis, MultibodyJointHandle,
+    MultibodyJointSet, NarrowPhase, PairFilterContext, PhysicsHooks, PhysicsPipeline, Point,
+    RevoluteJointBuilder, RigidBodyBuilder, RigidBodyHandle, RigidBodySet, SharedShape,
+    SolverFlags,
+};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use backend::{ColliderShape, CollisionFilter, Contact, LinkJoint, PhysicsBackend, Velocity};
+use body_tree::{wrap, Coord};
+use physics::WorldConfig;
+
+/// How strongly motors chase their target speed, before being limited by their max torque.
+/// Stiff enough to behave like the hard velocity constraint of an nphysics motor.
+const MOTOR_FACTOR: Real = 1.0e6;
+
+struct Link {
+    parent: RigidBodyHandle,
+    joint: Option<MultibodyJointHandle>,
+    /// Accumulated hinge angle, None if the link isn't revolute
+    angle: Option<Coord>,
+}
+
+pub struct RapierBackend {
+    config: WorldConfig,
+    pipeline: PhysicsPipeline,
+    params: IntegrationParameters,
+    gravity: Vector<Real>,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd: CCDSolver,
+    events: ChannelEventCollector,
+    collision_events: Receiver<CollisionEvent>,
+    // unused, but the collector needs somewhere to send them
+    _force_events: Receiver<ContactForceEvent>,
+    last_events: Vec<Contact<ColliderHandle>>,
+    ground: RigidBodyHandle,
+    links: HashMap<RigidBodyHandle, Link>,
+    forced: Vec<RigidBodyHandle>,
+}
+
+fn to_vector(v: &Vector3<Coord>) -> Vector<Real> {
+    Vector::new(v.x, v.y, v.z)
+}
+
+fn from_vector(v: &Vector<Real>) -> Vector3<Coord> {
+    Vector3::new(v.x, v.y, v.z)
+}
+
+fn to_isometry(iso: &Isometry3<Coord>) -> Isometry<Real> {
+    let t = &iso.translation.vector;
+    let q = &iso.rotation.quaternion().coords;
+    Isometry::from_parts(
+        rapier3d_f64::na::Translation3::new(t.x, t.y, t.z),
+        rapier3d_f64::na::UnitQuaternion::new_normalize(rapier3d_f64::na::Quaternion::new(
+            q[3], q[0], q[1], q[2],
+        )),
+    )
+}
+
+fn from_isometry(iso: &Isometry<Real>) -> Isometry3<Coord> {
+    let t = &iso.translation.vector;
+    let q = &iso.rotation.quaternion().coords;
+    Isometry3::from_parts(
+        Translation3::new(t.x, t.y, t.z),
+        Unit::new_normalize(Quaternion::new(q[3], q[0], q[1], q[2])),
+    )
+}
+
+/// Rapier has no blacklists, so they're applied by `FilterHooks`
+fn interaction_groups(filter: &CollisionFilter) -> InteractionGroups {
+    InteractionGroups::new(
+        Group::from_bits_truncate(filter.membership),
+        Group::from_bits_truncate(filter.whitelist),
+    )
+}
+
+/// Packed into a collider's user data for `FilterHooks`
+fn pack_filter(filter: &CollisionFilter) -> u128 {
+    u128::from(filter.membership)
+        | u128::from(filter.whitelist) << 32
+        | u128::from(filter.blacklist) << 64
+}
+
+fn unpack_filter(data: u128) -> CollisionFilter {
+    CollisionFilter {
+        membership: data as u32,
+        whitelist: (data >> 32) as u32,
+        blacklist: (data >> 64) as u32,
+    }
+}
+
+/// Angle of a rotation about the hinge's x axis, ignoring any swing the solver lets through
+fn hinge_twist(rotation: &UnitQuaternion<Coord>) -> Coord {
+    let q = &rotation.quaternion().coords;
+    wrap(2.0 * q[0].atan2(q[3]), -PI, PI)
+}
+
+/// Applies the whole of each collider's `CollisionFilter`, including the blacklists that
+/// `InteractionGroups` can't express for colliders in several groups
+struct FilterHooks;
+
+impl PhysicsHooks for FilterHooks {
+    fn filter_contact_pair(&self, context: &PairFilterContext) -> Option<SolverFlags> {
+        // replaces rapier's own filter, which skips pairs with nothing dynamic
+        let dynamic =
+            |body: Option<RigidBodyHandle>| body.map_or(false, |b| context.bodies[b].is_dynamic());
+        if !dynamic(context.rigid_body1) && !dynamic(context.rigid_body2) {
+            return None;
+        }
+
+        let a = unpack_filter(context.colliders[context.collider1].user_data);
+        let b = unpack_filter(context.colliders[context.collider2].user_data);
+        if a.can_interact_with(&b) {
+            Some(SolverFlags::COMPUTE_IMPULSES)
+        } else {
+            None
+        }
+    }
+}
+
+impl RapierBackend {
+    /// Accumulates each hinge's twist since the last step, so angles keep counting past a
+    /// full turn like nphysics' revolute joints do
+    fn track_angles(&mut self) {
+        let bodies = &self.bodies;
+        for (body, link) in &mut self.links {
+            if let Some(ref mut angle) = link.angle {
+                let parent = from_isometry(bodies[link.parent].position()).rotation;
+                let child = from_isometry(bodies[*body].position()).rotation;
+                let twist = hinge_twist(&(parent.inverse() * child));
+                *angle += wrap(twist - *angle, -PI, PI);
+            }
+        }
+    }
+
+    fn remove_links_of(&mut self, parent: RigidBodyHandle) {
+        let children: Vec<RigidBodyHandle> = self
+            .links
+            .iter()
+            .filter(|(_, link)| link.parent == parent)
+            .map(|(child, _)| *child)
+            .collect();
+        for child in children {
+            self.remove_body(child);
+        }
+    }
+}
+
+impl PhysicsBackend for RapierBackend {
+    type Body = RigidBodyHandle;
+    type Collider = ColliderHandle;
+
+    fn new(config: &WorldConfig) -> Self {
+        let mut params = IntegrationParameters::default();
+        params.dt = config.timestep;
+        params.max_velocity_iterations = config.velocity_iterations;
+        // the closest thing to nphysics' position correction
+        params.max_stabilization_iterations = config.position_iterations;
+
+        let (collision_send, collision_events) = unbounded();
+        let (force_send, force_events) = unbounded();
+        let mut bodies = RigidBodySet::new();
+        let ground = bodies.insert(RigidBodyBuilder::fixed().build());
+
+        Self {
+            config: config.clone(),
+            pipeline: PhysicsPipeline::new(),
+            params,
+            gravity: to_vector(&config.gravity),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies,
+            colliders: ColliderSet::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd: CCDSolver::new(),
+            events: ChannelEventCollector::new(collision_send, force_send),
+            collision_events,
+            _force_events: force_events,
+            last_events: Vec::new(),
+            ground,
+            links: HashMap::new(),
+            forced: Vec::new(),
+        }
+    }
+
+    fn set_gravity(&mut self, gravity: Vector3<Coord>) {
+        self.gravity = to_vector(&gravity);
+    }
+
+    fn step(&mut self) {
+        self.pipeline.step(
+            &self.gravity,
+            &self.params,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd,
+            None,
+            &FilterHooks,
+            &self.events,
+        );
+
+        self.track_angles();
+
+        for body in self.forced.drain(..) {
+            if let Some(body) = self.bodies.get_mut(body) {
+                body.reset_forces(false);
+                body.reset_torques(false);
+            }
+        }
+
+        self.last_events = self
+            .collision_events
+            .try_iter()
+            .map(|event| match event {
+                CollisionEvent::Started(a, b, _) => Contact::Started(a, b),
+                CollisionEvent::Stopped(a, b, _) => Contact::Stopped(a, b),
+            })
+            .collect();
+    }
+
+    fn clear(&mut self) {
+        let gravity = self.gravity;
+        *self = Self::new(&self.config);
+        self.gravity = gravity;
+    }
+
+    fn ground(&self) -> RigidBodyHandle {
+        self.ground
+    }
+
+    fn add_link(
+        &mut self,
+        parent: RigidBodyHandle,
+        joint: &LinkJoint,
+        // mass comes from the collider added afterwards
+        _half_extents: &Vector3<Coord>,
+    ) -> RigidBodyHandle {
+        let parent_pos = *self.bodies[parent].position();
+        let (position, joint) = match *joint {
+            LinkJoint::Free(pos) => (to_isometry(&pos), None),
+            LinkJoint::Fixed(pos) => {
+                let frame = to_isometry(&pos);
+                let joint: GenericJoint = FixedJointBuilder::new().local_frame1(frame).into();
+                (parent_pos * frame, Some((joint, false)))
+            }
+            LinkJoint::Revolute {
+                offset,
+                motor_speed,
+                max_torque,
+            } => {
+                let anchor = Point::from(to_vector(&offset));
+                let joint: GenericJoint = RevoluteJointBuilder::new(Vector::x_axis())
+                    .local_anchor1(anchor)
+                    .local_anchor2(Point::origin())
+                    .motor_velocity(motor_speed, MOTOR_FACTOR)
+                    // Rapier caps the impulse of each step at max force * dt
+                    .motor_max_force(max_torque / self.params.dt)
+                    .into();
+                let position = parent_pos * Isometry::translation(offset.x, offset.y, offset.z);
+                (position, Some((joint, true)))
+            }
+        };
+
+        let body = self
+            .bodies
+            .insert(RigidBodyBuilder::dynamic().position(position).build());
+
+        if let Some((joint, revolute)) = joint {
+            let handle = self.multibody_joints.insert(parent, body, joint, true);
+            let angle = if revolute { Some(0.0) } else { None };
+            self.links.insert(
+                body,
+                Link {
+                    parent,
+                    joint: handle,
+                    angle,
+                },
+            );
+        }
+        body
+    }
+
+    fn remove_body(&mut self, body: RigidBodyHandle) {
+        self.remove_links_of(body);
+        self.links.remove(&body);
+        self.bodies.remove(
+            body,
+            &mut self.islands,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            true,
+        );
+    }
+
+    fn add_collider(
+        &mut self,
+        body: RigidBodyHandle,
+        shape: &ColliderShape,
+        position: &Isometry3<Coord>,
+        _margin: Coord,
+        filter: &CollisionFilter,
+    ) -> ColliderHandle {
+        let builder = match shape {
+            ColliderShape::Cuboid(h) => ColliderBuilder::cuboid(h.x, h.y, h.z),
+            ColliderShape::Cuboids(cuboids) => ColliderBuilder::compound(
+                cuboids
+                    .iter()
+                    .map(|(pos, h)| (to_isometry(pos), SharedShape::cuboid(h.x, h.y, h.z)))
+                    .collect(),
+            ),
+        };
+
+        let collider = builder
+            .position(to_isometry(position))
+            .density(1.0)
+            .collision_groups(interaction_groups(filter))
+            .user_data(pack_filter(filter))
+            .active_hooks(ActiveHooks::FILTER_CONTACT_PAIRS)
+            .active_events(ActiveEvents::COLLISION_EVENTS)
+            .build();
+        self.colliders
+            .insert_with_parent(collider, body, &mut self.bodies)
+    }
+
+    fn collider_position(&self, collider: ColliderHandle) -> Option<Isometry3<Coord>> {
+        self.colliders
+            .get(collider)
+            .map(|c| from_isometry(c.position()))
+    }
+
+    fn collider_body(&self, collider: ColliderHandle) -> Option<RigidBodyHandle> {
+        self.colliders.get(collider)?.parent()
+    }
+
+    fn body_position(&self, body: RigidBodyHandle) -> Isometry3<Coord> {
+        from_isometry(self.bodies[body].position())
+    }
+
+    fn body_velocity(&self, body: RigidBodyHandle) -> Velocity {
+        let body = &self.bodies[body];
+        Velocity {
+            linear: from_vector(body.linvel()),
+            angular: from_vector(body.angvel()),
+        }
+    }
+
+    fn is_active(&self, body: RigidBodyHandle) -> bool {
+        !self.bodies[body].is_sleeping()
+    }
+
+    fn apply_force(
+        &mut self,
+        body: RigidBodyHandle,
+        force: Vector3<Coord>,
+        torque: Vector3<Coord>,
+    ) {
+        if let Some(rb) = self.bodies.get_mut(body) {
+            rb.add_force(to_vector(&force), true);
+            rb.add_torque(to_vector(&torque), true);
+            self.forced.push(body);
+        }
+    }
+
+    fn joint_angle(&self, body: RigidBodyHandle) -> Option<Coord> {
+        self.links.get(&body)?.angle
+    }
+
+    /// Rapier doesn't keep the impulses of the joints inside a multibody
+    fn motor_impulse(&self, _body: RigidBodyHandle) -> Option<Coord> {
+        None
+    }
+
+    fn disable_motor(&mut self, body: RigidBodyHandle) {
+        let handle = match self.links.get(&body) {
+            Some(Link {
+                joint: Some(handle),
+                angle: Some(_),
+                ..
+            }) => *handle,
+            _ => return,
+        };
+
+        if let Some((multibody, link_id)) = self.multibody_joints.get_mut(handle) {
+            if let Some(link) = multibody.link_mut(link_id) {
+                link.joint.data.set_motor_max_force(JointAxis::AngX, 0.0);
+            }
+        }
+    }
+
+    fn contact_events(&self) -> Vec<Contact<ColliderHandle>> {
+        self.last_events.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn twist() {
+        let about_x = |angle| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), angle);
+        for angle in &[0.0, 0.5, -1.0, 3.0, -3.0] {
+            assert!((hinge_twist(&about_x(*angle)) - angle).abs() < 1e-9);
+        }
+
+        // a little swing about another axis doesn't show up as twist
+        let swing = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.01);
+        assert!((hinge_twist(&(about_x(0.5) * swing)) - 0.5).abs() < 1e-3);
+    }
+}
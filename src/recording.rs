@@ -0,0 +1,285 @@
+//! Per-tick poses of every object in a `World`, so that a run can be watched again exactly as
+//! it happened without re-running the physics.
+//!
+//! Recordings are JSON. Objects are described once, and each frame only holds the poses of the
+//! objects that can move.
+
+use nalgebra::{Isometry3, Quaternion, Translation3, Unit};
+use serde_json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use backend::PhysicsBackend;
+use body_tree::Coord;
+use nphysics_backend::NPhysicsBackend;
+use physics::{Colour, CreatureHandle, ObjectShape, World};
+
+/// Translation followed by the rotation quaternion's i, j, k and w. Single precision is plenty
+/// for watching
+pub type Pose = [f32; 7];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedObject {
+    pub shape: ObjectShape,
+    pub colour: Colour,
+    pub creature: Option<CreatureHandle>,
+    /// First and last frames the object is alive in, inclusive
+    pub first_frame: usize,
+    pub last_frame: usize,
+    /// Set for the ground and terrain, which never move and so are left out of frames
+    pub fixed_pose: Option<Pose>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Frame {
+    /// Every moving object alive in this frame, as an index into the recording's objects
+    pub poses: Vec<(usize, Pose)>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    /// Seconds per frame
+    pub timestep: Coord,
+    pub objects: Vec<RecordedObject>,
+    pub frames: Vec<Frame>,
+}
+
+/// Captures a frame of a world whenever asked, usually after every tick
+pub struct Recorder<B: PhysicsBackend = NPhysicsBackend> {
+    recording: Recording,
+    /// Objects seen so far in the world's current generation
+    ids: HashMap<(B::Collider, Option<CreatureHandle>), usize>,
+    generation: usize,
+}
+
+/// Plays back a recording one frame at a time, in either direction
+pub struct Replay {
+    recording: Recording,
+    frame: usize,
+}
+
+/// A `Pose` at full precision, for comparing poses that must match closely
+pub type PrecisePose = [Coord; 7];
+
+pub fn to_precise_pose(iso: &Isometry3<Coord>) -> PrecisePose {
+    let t = &iso.translation.vector;
+    let q = &iso.rotation.quaternion().coords;
+    [t.x, t.y, t.z, q[0], q[1], q[2], q[3]]
+}
+
+pub fn from_precise_pose(pose: &PrecisePose) -> Isometry3<Coord> {
+    Isometry3::from_parts(
+        Translation3::new(pose[0], pose[1], pose[2]),
+        Unit::new_normalize(Quaternion::new(pose[6], pose[3], pose[4], pose[5])),
+    )
+}
+
+pub fn to_pose(iso: &Isometry3<Coord>) -> Pose {
+    to_precise_pose(iso).map(|p| p as f32)
+}
+
+pub fn from_pose(pose: &Pose) -> Isometry3<Coord> {
+    from_precise_pose(&pose.map(Coord::from))
+}
+
+impl Recording {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn read<R: Read>(reader: R) -> io::Result<Self> {
+        serde_json::from_reader(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        serde_json::to_writer(&mut writer, self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writer.flush()
+    }
+
+    /// Length in seconds
+    pub fn duration(&self) -> Coord {
+        self.frames.len() as Coord * self.timestep
+    }
+}
+
+impl<B: PhysicsBackend> Default for Recorder<B> {
+    fn default() -> Self {
+        Self {
+            recording: Recording::default(),
+            ids: HashMap::new(),
+            generation: 0,
+        }
+    }
+}
+
+impl<B: PhysicsBackend> Recorder<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the current state of the world as the next frame
+    pub fn record(&mut self, world: &World<B>) {
+        if world.generation() != self.generation {
+            self.ids.clear();
+            self.generation = world.generation();
+        }
+
+        let index = self.recording.frames.len();
+        let mut frame = Frame::default();
+        let objects = &mut self.recording.objects;
+        for (collider, object, position, _) in world.colliders() {
+            let id = *self
+                .ids
+                .entry((collider, object.creature))
+                .or_insert_with(|| {
+                    let fixed = object.creature.is_none();
+                    objects.push(RecordedObject {
+                        shape: object.shape.clone(),
+                        colour: object.colour,
+                        creature: object.creature,
+                        first_frame: index,
+                        last_frame: index,
+                        fixed_pose: if fixed {
+                            Some(to_pose(&position))
+                        } else {
+                            None
+                        },
+                    });
+                    objects.len() - 1
+                });
+
+            let recorded = &mut objects[id];
+            recorded.last_frame = index;
+            if recorded.fixed_pose.is_none() {
+                frame.poses.push((id, to_pose(&position)));
+            }
+        }
+
+        self.recording.timestep = world.config().timestep;
+        self.recording.frames.push(frame);
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            frame: 0,
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.recording.frames.len()
+    }
+
+    /// Clamped to the last frame
+    pub fn seek(&mut self, frame: usize) {
+        self.frame = frame.min(self.frame_count().saturating_sub(1));
+    }
+
+    /// Returns false if already at the last frame
+    pub fn step_forward(&mut self) -> bool {
+        let before = self.frame;
+        self.seek(before + 1);
+        self.frame != before
+    }
+
+    /// Returns false if already at the first frame
+    pub fn step_back(&mut self) -> bool {
+        let before = self.frame;
+        self.seek(before.saturating_sub(1));
+        self.frame != before
+    }
+
+    /// Every object alive in the current frame with its position, identified by its index in
+    /// the recording
+    pub fn poses(&self) -> impl Iterator<Item = (usize, &RecordedObject, Isometry3<Coord>)> {
+        let frame = self.frame;
+        let objects = &self.recording.objects;
+        let fixed = objects.iter().enumerate().filter_map(move |(id, o)| {
+            let pose = o.fixed_pose.as_ref()?;
+            if o.first_frame <= frame && frame <= o.last_frame {
+                Some((id, o, from_pose(pose)))
+            } else {
+                None
+            }
+        });
+        let moving = self
+            .recording
+            .frames
+            .get(frame)
+            .into_iter()
+            .flat_map(|f| f.poses.iter())
+            .map(move |(id, pose)| (*id, &objects[*id], from_pose(pose)));
+
+        fixed.chain(moving)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body_tree::tree;
+    use physics::PhysicalRealiser;
+    use std::io::Cursor;
+
+    #[test]
+    fn record_and_replay() {
+        let mut world: World = World::default();
+        world.clear();
+        {
+            let mut r = PhysicalRealiser::new(&mut world);
+            r.realise(&tree::grow_random_tree(2));
+        }
+
+        let mut recorder = Recorder::new();
+        for _ in 0..30 {
+            world.tick();
+            recorder.record(&world);
+        }
+
+        let mut bytes = Cursor::new(Vec::new());
+        recorder.finish().write(&mut bytes).unwrap();
+        bytes.set_position(0);
+        let mut replay = Replay::new(Recording::read(&mut bytes).unwrap());
+
+        assert_eq!(replay.frame_count(), 30);
+        replay.seek(100);
+        assert_eq!(replay.frame(), 29);
+        assert!(!replay.step_forward());
+
+        let live: Vec<Isometry3<Coord>> = world.colliders().map(|(_, _, pos, _)| pos).collect();
+        let replayed: Vec<Isometry3<Coord>> = replay.poses().map(|(_, _, pos)| pos).collect();
+        assert_eq!(live.len(), replayed.len());
+        for pos in live {
+            let closest = replayed
+                .iter()
+                .map(|r| (r.translation.vector - pos.translation.vector).norm())
+                .fold(::std::f64::INFINITY, Coord::min);
+            assert!(closest < 1e-4);
+        }
+    }
+}
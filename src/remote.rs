@@ -0,0 +1,376 @@
+//! Evaluation spread over worker processes.
+//!
+//! Messages are JSON, each prefixed with its length as a big endian u32. A coordinator sends
+//! `Request`s over any number of connections and each worker replies to every `Evaluate` with
+//! a `Response`, so the same protocol works over TCP and unix sockets.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+use body_tree::tree::BodyTree;
+use body_tree::Population;
+use evaluate::{self, EvaluationSettings, Score};
+
+/// Refuse to allocate for anything bigger, it's surely garbage
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+/// Longest a worker waits on a coordinator to send or receive a frame before hanging up on it.
+/// Generous, as coordinators may sit on an idle connection between generations
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+    Evaluate {
+        id: usize,
+        tree: BodyTree,
+        settings: EvaluationSettings,
+    },
+    Shutdown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+    Score { id: usize, score: Score },
+}
+
+/// A connection a worker can serve a coordinator over
+pub trait Connection: Read + Write {
+    fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)
+    }
+}
+
+/// Jobs shared between the threads talking to workers
+struct Jobs {
+    queue: VecDeque<(usize, BodyTree)>,
+    /// Jobs without a score yet, including those being evaluated
+    unfinished: usize,
+}
+
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, msg: &T) -> io::Result<()> {
+    let bytes =
+        serde_json::to_vec(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if bytes.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "message too large",
+        ));
+    }
+
+    let len = bytes.len() as u32;
+    let header = [
+        (len >> 24) as u8,
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+    ];
+    writer.write_all(&header)?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+/// Returns None if the stream was closed cleanly before a new frame
+pub fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> io::Result<Option<T>> {
+    let mut header = [0u8; 4];
+    match reader.read_exact(&mut header) {
+        Ok(_) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = header
+        .iter()
+        .fold(0usize, |len, b| (len << 8) | usize::from(*b));
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes is too large", len),
+        ));
+    }
+
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes)?;
+    serde_json::from_slice(&bytes)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Evaluates requests from a single coordinator until it disconnects or asks us to stop.
+/// Returns true if a shutdown was requested.
+pub fn serve_connection<S: Read + Write>(stream: &mut S) -> io::Result<bool> {
+    loop {
+        match read_frame(stream)? {
+            Some(Request::Evaluate { id, tree, settings }) => {
+                let score = evaluate::evaluate(&tree, &settings);
+                write_frame(stream, &Response::Score { id, score })?;
+            }
+            Some(Request::Shutdown) => return Ok(true),
+            None => return Ok(false),
+        }
+    }
+}
+
+/// Serves coordinators one at a time until one requests a shutdown. Accepts the `incoming()`
+/// of either a `TcpListener` or `UnixListener`. A coordinator that fails or goes quiet for too
+/// long is logged and dropped, and the next one served.
+pub fn serve<S, I>(incoming: I)
+where
+    S: Connection,
+    I: IntoIterator<Item = io::Result<S>>,
+{
+    for stream in incoming {
+        let result = stream.and_then(|mut stream| {
+            stream.set_timeout(Some(CONNECTION_TIMEOUT))?;
+            serve_connection(&mut stream)
+        });
+        match result {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(e) => eprintln!("connection failed: {}", e),
+        }
+    }
+}
+
+/// Evaluates the population across the given worker connections, returning scores in
+/// population order. If a worker fails its job is handed back to any workers still running,
+/// which wait for every job to be scored before stopping, and an error is only returned if
+/// some individuals were left unevaluated.
+pub fn evaluate_population<S>(
+    workers: Vec<S>,
+    population: &Population,
+    settings: &EvaluationSettings,
+) -> io::Result<Vec<Score>>
+where
+    S: Read + Write + Send + 'static,
+{
+    let jobs = Jobs {
+        queue: population.iter().cloned().enumerate().collect(),
+        unfinished: population.len(),
+    };
+    let jobs = Arc::new((Mutex::new(jobs), Condvar::new()));
+    let (tx, rx) = mpsc::channel();
+
+    let handles: Vec<_> = workers
+        .into_iter()
+        .map(|mut worker| {
+            let jobs = jobs.clone();
+            let tx = tx.clone();
+            let settings = *settings;
+            thread::spawn(move || -> io::Result<()> {
+                let (ref jobs, ref changed) = *jobs;
+                loop {
+                    // the queue can be empty while a job is still out on another worker, which
+                    // may fail and hand it back
+                    let (id, tree) = {
+                        let mut jobs = jobs.lock().unwrap();
+                        loop {
+                            if let Some(job) = jobs.queue.pop_front() {
+                                break job;
+                            }
+                            if jobs.unfinished == 0 {
+                                return Ok(());
+                            }
+                            jobs = changed.wait(jobs).unwrap();
+                        }
+                    };
+
+                    match evaluate_on_worker(&mut worker, id, &tree, settings) {
+                        Ok(score) => {
+                            tx.send((id, score)).unwrap();
+                            jobs.lock().unwrap().unfinished -= 1;
+                            changed.notify_all();
+                        }
+                        Err(e) => {
+                            // let someone else have a go
+                            jobs.lock().unwrap().queue.push_back((id, tree));
+                            changed.notify_all();
+                            return Err(e);
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let mut scores = vec![None; population.len()];
+    for (id, score) in rx {
+        scores[id] = Some(score);
+    }
+
+    let mut error = None;
+    for handle in handles {
+        if let Err(e) = handle.join().expect("Worker thread panicked") {
+            error = Some(e);
+        }
+    }
+
+    match scores.iter().cloned().collect::<Option<Vec<Score>>>() {
+        Some(scores) => Ok(scores),
+        None => Err(error
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::Other, "no workers to evaluate on"))),
+    }
+}
+
+fn evaluate_on_worker<S: Read + Write>(
+    worker: &mut S,
+    id: usize,
+    tree: &BodyTree,
+    settings: EvaluationSettings,
+) -> io::Result<Score> {
+    // TODO avoid this clone
+    let request = Request::Evaluate {
+        id,
+        tree: tree.clone(),
+        settings,
+    };
+    write_frame(worker, &request)?;
+
+    match read_frame(worker)? {
+        Some(Response::Score {
+            id: reply_id,
+            score,
+        }) if reply_id == id => Ok(score),
+        Some(Response::Score { id: reply_id, .. }) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected score for {} but got {}", id, reply_id),
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "worker disconnected",
+        )),
+    }
+}
+
+/// Asks the worker process to stop serving and exit
+pub fn shutdown<W: Write>(worker: &mut W) -> io::Result<()> {
+    write_frame(worker, &Request::Shutdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body_tree::tree;
+    use std::io::Cursor;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn frames() {
+        let mut cursor = Cursor::new(Vec::new());
+        write_frame(&mut cursor, &Response::Score { id: 4, score: 2.5 }).unwrap();
+        write_frame(&mut cursor, &Response::Score { id: 5, score: 1.0 }).unwrap();
+        cursor.set_position(0);
+
+        match read_frame(&mut cursor).unwrap() {
+            Some(Response::Score { id: 4, score }) => assert_eq!(score, 2.5),
+            r => panic!("unexpected frame {:?}", r),
+        }
+        match read_frame(&mut cursor).unwrap() {
+            Some(Response::Score { id: 5, score }) => assert_eq!(score, 1.0),
+            r => panic!("unexpected frame {:?}", r),
+        }
+        assert!(read_frame::<_, Response>(&mut cursor).unwrap().is_none());
+    }
+
+    #[test]
+    fn local_workers() {
+        let pop: Population = (0..5).map(|_| tree::grow_random_tree(2)).collect();
+        let settings = EvaluationSettings {
+            ticks: 30,
+            ..EvaluationSettings::default()
+        };
+
+        let mut servers = Vec::new();
+        let mut workers = Vec::new();
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            servers.push(thread::spawn(move || serve(listener.incoming())));
+            workers.push(TcpStream::connect(addr).unwrap());
+        }
+
+        let controls: Vec<TcpStream> = workers.iter().map(|w| w.try_clone().unwrap()).collect();
+        let scores = evaluate_population(workers, &pop, &settings).unwrap();
+        assert_eq!(scores, evaluate::evaluate_population(&pop, &settings));
+
+        for mut control in controls {
+            shutdown(&mut control).unwrap();
+        }
+        for server in servers {
+            server.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn failed_worker() {
+        let pop: Population = (0..4).map(|_| tree::grow_random_tree(2)).collect();
+        let settings = EvaluationSettings {
+            ticks: 30,
+            ..EvaluationSettings::default()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let good = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = thread::spawn(move || serve(listener.incoming()));
+
+        // hangs up without replying to anything
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let bad = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        drop(listener.accept().unwrap());
+
+        let mut control = good.try_clone().unwrap();
+        let scores = evaluate_population(vec![bad, good], &pop, &settings).unwrap();
+        assert_eq!(scores, evaluate::evaluate_population(&pop, &settings));
+
+        shutdown(&mut control).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn bad_coordinator() {
+        let pop: Population = (0..2).map(|_| tree::grow_random_tree(2)).collect();
+        let settings = EvaluationSettings {
+            ticks: 30,
+            ..EvaluationSettings::default()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || serve(listener.incoming()));
+
+        // announces a frame too big to accept
+        let mut bad = TcpStream::connect(addr).unwrap();
+        bad.write_all(&[0xff; 4]).unwrap();
+
+        let good = TcpStream::connect(addr).unwrap();
+        let mut control = good.try_clone().unwrap();
+        let scores = evaluate_population(vec![good], &pop, &settings).unwrap();
+        assert_eq!(scores, evaluate::evaluate_population(&pop, &settings));
+
+        shutdown(&mut control).unwrap();
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Detection of creatures whose simulation has blown up, usually from interpenetrating parts
+//! being violently pushed apart.
+
+use nalgebra::{Isometry3, Vector3};
+
+use body_tree::Coord;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Invalidity {
+    NonFinitePosition { part: usize },
+    ExcessiveVelocity { part: usize, speed: Coord },
+    ExcessiveAngularVelocity { part: usize, speed: Coord },
+    /// Kinetic energy per unit mass jumped by this much in a single tick
+    EnergyBlowUp { jump: Coord },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SanityLimits {
+    pub max_speed: Coord,
+    pub max_angular_speed: Coord,
+    /// Maximum increase in kinetic energy per unit mass in a single tick
+    pub max_energy_jump: Coord,
+}
+
+impl Default for SanityLimits {
+    fn default() -> Self {
+        Self {
+            max_speed: 50.0,
+            max_angular_speed: 100.0,
+            max_energy_jump: 200.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PartSample {
+    pub position: Isometry3<Coord>,
+    pub linear: Vector3<Coord>,
+    pub angular: Vector3<Coord>,
+    pub mass: Coord,
+}
+
+/// Kinetic energy per unit mass, ignoring rotation
+pub fn specific_kinetic_energy(parts: &[PartSample]) -> Coord {
+    let mass: Coord = parts.iter().map(|p| p.mass).sum();
+    if mass <= 0.0 {
+        return 0.0;
+    }
+
+    let energy: Coord = parts
+        .iter()
+        .map(|p| 0.5 * p.mass * p.linear.norm_squared())
+        .sum();
+    energy / mass
+}
+
+/// Returns the new specific kinetic energy to pass in next tick, or why the creature is invalid
+pub fn check(
+    limits: &SanityLimits,
+    parts: &[PartSample],
+    last_energy: Coord,
+) -> Result<Coord, Invalidity> {
+    for (i, part) in parts.iter().enumerate() {
+        let translation = &part.position.translation.vector;
+        let finite = translation.iter().all(|c| c.is_finite())
+            && part.position.rotation.coords.iter().all(|c| c.is_finite());
+        if !finite {
+            return Err(Invalidity::NonFinitePosition { part: i });
+        }
+
+        // nan speeds fail these comparisons, so are caught too
+        let speed = part.linear.norm();
+        if !(speed <= limits.max_speed) {
+            return Err(Invalidity::ExcessiveVelocity { part: i, speed });
+        }
+
+        let angular_speed = part.angular.norm();
+        if !(angular_speed <= limits.max_angular_speed) {
+            return Err(Invalidity::ExcessiveAngularVelocity {
+                part: i,
+                speed: angular_speed,
+            });
+        }
+    }
+
+    let energy = specific_kinetic_energy(parts);
+    let jump = energy - last_energy;
+    if jump > limits.max_energy_jump {
+        return Err(Invalidity::EnergyBlowUp { jump });
+    }
+
+    Ok(energy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(linear: Vector3<Coord>) -> PartSample {
+        PartSample {
+            position: Isometry3::identity(),
+            linear,
+            angular: Vector3::zeros(),
+            mass: 2.0,
+        }
+    }
+
+    #[test]
+    fn sane() {
+        let limits = SanityLimits::default();
+        let parts = [part(Vector3::new(1.0, 0.0, 0.0)), part(Vector3::zeros())];
+        let energy = check(&limits, &parts, 0.0).unwrap();
+        assert!((energy - 0.25).abs() < 0.0001);
+    }
+
+    #[test]
+    fn insane() {
+        let limits = SanityLimits {
+            max_speed: 10.0,
+            max_angular_speed: 10.0,
+            max_energy_jump: 5.0,
+        };
+
+        let mut nan = part(Vector3::zeros());
+        nan.position.translation.vector.y = ::std::f64::NAN;
+        assert_eq!(
+            check(&limits, &[part(Vector3::zeros()), nan], 0.0),
+            Err(Invalidity::NonFinitePosition { part: 1 })
+        );
+
+        match check(&limits, &[part(Vector3::new(0.0, 20.0, 0.0))], 0.0) {
+            Err(Invalidity::ExcessiveVelocity { part: 0, .. }) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        // 0.5 * 8^2 = 32
+        match check(&limits, &[part(Vector3::new(8.0, 0.0, 0.0))], 20.0) {
+            Err(Invalidity::EnergyBlowUp { jump }) => assert!((jump - 12.0).abs() < 0.0001),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(check(&limits, &[part(Vector3::new(8.0, 0.0, 0.0))], 30.0).is_ok());
+    }
+}
@@ -0,0 +1,328 @@
+//! NEAT-style speciation: the population is clustered into species of similar body trees, and
+//! individuals share fitness with the rest of their species, so a new morphology isn't wiped
+//! out by established ones before its joints have been tuned.
+
+use rand::Rng;
+
+use body_tree::body::def::{Joint, ParamHolder, RangedParam, ShapeDefinition};
+use body_tree::tree::{BodyTree, EdgeRef, NodeIndex};
+use body_tree::{Coord, Population};
+use evaluate::Score;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DistanceWeights {
+    /// Cost of each node present in only one of the trees
+    pub node: Coord,
+    /// Cost of the mean difference between the params of aligned shapes
+    pub shape: Coord,
+    /// Cost of the mean difference between the params of aligned joints, or of the joints
+    /// being different kinds
+    pub joint: Coord,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SpeciationSettings {
+    pub weights: DistanceWeights,
+    /// Trees closer than this to a species' representative belong to it
+    pub threshold: Coord,
+}
+
+#[derive(Debug, Clone)]
+pub struct Species {
+    /// Trees are compared against this to decide whether they belong
+    pub representative: BodyTree,
+    /// Indices into the population
+    pub members: Vec<usize>,
+}
+
+/// Species carried over between generations, so they keep their identity
+pub struct Speciation {
+    settings: SpeciationSettings,
+    species: Vec<Species>,
+}
+
+impl Default for DistanceWeights {
+    fn default() -> Self {
+        Self {
+            node: 1.0,
+            shape: 1.0,
+            joint: 0.5,
+        }
+    }
+}
+
+impl Default for SpeciationSettings {
+    fn default() -> Self {
+        Self {
+            weights: DistanceWeights::default(),
+            threshold: 1.5,
+        }
+    }
+}
+
+/// Unscaled params, all in 0..1
+fn shape_params(shape: &ShapeDefinition) -> Vec<Coord> {
+    let mut shape = shape.clone();
+    (0..shape.param_count())
+        .map(|i| shape.get_param(i).get())
+        .collect()
+}
+
+fn shape_distance(a: &ShapeDefinition, b: &ShapeDefinition) -> Coord {
+    let (a, b) = (shape_params(a), shape_params(b));
+    let total: Coord = a.iter().zip(&b).map(|(a, b)| (a - b).abs()).sum();
+    total / a.len().max(1) as Coord
+}
+
+fn joint_distance(a: &Joint, b: &Joint) -> Coord {
+    match (a, b) {
+        (Joint::Fixed, Joint::Fixed) | (Joint::Ground, Joint::Ground) => 0.0,
+        (
+            Joint::Rotational {
+                torque: ta,
+                max_speed: sa,
+            },
+            Joint::Rotational {
+                torque: tb,
+                max_speed: sb,
+            },
+        ) => ((ta.get() - tb.get()).abs() + (sa.get() - sb.get()).abs()) / 2.0,
+        _ => 1.0,
+    }
+}
+
+fn subtree_size(tree: &BodyTree, node: NodeIndex) -> usize {
+    1 + tree
+        .get_children(node)
+        .map(|edge| subtree_size(tree, edge.source()))
+        .sum::<usize>()
+}
+
+fn children(tree: &BodyTree, node: NodeIndex) -> Vec<(NodeIndex, Joint)> {
+    tree.get_children(node)
+        .map(|edge| (edge.source(), *edge.weight()))
+        .collect()
+}
+
+/// Distance between two aligned subtrees, aligning their children in order with the fewest
+/// insertions and deletions, as in a top-down tree edit distance
+fn subtree_distance(
+    a: &BodyTree,
+    a_node: NodeIndex,
+    b: &BodyTree,
+    b_node: NodeIndex,
+    weights: &DistanceWeights,
+) -> Coord {
+    let shape = weights.shape * shape_distance(a.shape(a_node), b.shape(b_node));
+
+    let (a_children, b_children) = (children(a, a_node), children(b, b_node));
+    let removed = |i: usize| weights.node * subtree_size(a, a_children[i].0) as Coord;
+    let added = |j: usize| weights.node * subtree_size(b, b_children[j].0) as Coord;
+
+    // cost[i][j] aligns the first i children of a with the first j of b
+    let mut cost = vec![vec![0.0; b_children.len() + 1]; a_children.len() + 1];
+    for i in 1..=a_children.len() {
+        cost[i][0] = cost[i - 1][0] + removed(i - 1);
+    }
+    for j in 1..=b_children.len() {
+        cost[0][j] = cost[0][j - 1] + added(j - 1);
+    }
+    for i in 1..=a_children.len() {
+        for j in 1..=b_children.len() {
+            let (a_child, a_joint) = &a_children[i - 1];
+            let (b_child, b_joint) = &b_children[j - 1];
+            let aligned = cost[i - 1][j - 1]
+                + weights.joint * joint_distance(a_joint, b_joint)
+                + subtree_distance(a, *a_child, b, *b_child, weights);
+            cost[i][j] = aligned
+                .min(cost[i - 1][j] + removed(i - 1))
+                .min(cost[i][j - 1] + added(j - 1));
+        }
+    }
+
+    shape + cost[a_children.len()][b_children.len()]
+}
+
+/// Genome distance between two trees, 0 for identical trees
+pub fn distance(a: &BodyTree, b: &BodyTree, weights: &DistanceWeights) -> Coord {
+    subtree_distance(a, a.root(), b, b.root(), weights)
+}
+
+/// Each score divided by the size of its species
+pub fn shared_scores(species: &[Species], scores: &[Score]) -> Vec<Score> {
+    let mut shared = scores.to_vec();
+    for s in species {
+        let size = s.members.len() as Score;
+        for i in &s.members {
+            shared[*i] = scores[*i] / size;
+        }
+    }
+    shared
+}
+
+/// Number of children each species should have out of `total`, in proportion to the sum of its
+/// members' shared scores
+pub fn offspring_counts(species: &[Species], shared: &[Score], total: usize) -> Vec<usize> {
+    if species.is_empty() {
+        return Vec::new();
+    }
+
+    let sums: Vec<Score> = species
+        .iter()
+        .map(|s| s.members.iter().map(|i| shared[*i].max(0.0)).sum())
+        .collect();
+    let overall: Score = sums.iter().sum();
+    let exact: Vec<Score> = sums
+        .iter()
+        .map(|sum| {
+            if overall > 0.0 {
+                sum / overall * total as Score
+            } else {
+                total as Score / species.len() as Score
+            }
+        })
+        .collect();
+
+    // round down, then hand out what's left by largest remainder
+    let mut counts: Vec<usize> = exact.iter().map(|e| e.floor() as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..species.len()).collect();
+    by_remainder.sort_by(|a, b| {
+        let (ra, rb) = (exact[*a] - exact[*a].floor(), exact[*b] - exact[*b].floor());
+        rb.partial_cmp(&ra).unwrap_or(::std::cmp::Ordering::Equal)
+    });
+    let assigned: usize = counts.iter().sum();
+    for i in by_remainder.into_iter().take(total - assigned) {
+        counts[i] += 1;
+    }
+    counts
+}
+
+impl Speciation {
+    pub fn new(settings: SpeciationSettings) -> Self {
+        Self {
+            settings,
+            species: Vec::new(),
+        }
+    }
+
+    pub fn species(&self) -> &[Species] {
+        &self.species
+    }
+
+    /// Assigns every tree to the first species whose representative is close enough, founding
+    /// a new species otherwise. Species left without members die out, and the rest take their
+    /// first member as the representative for the next generation.
+    pub fn speciate(&mut self, population: &Population) -> &[Species] {
+        for s in &mut self.species {
+            s.members.clear();
+        }
+
+        for (i, tree) in population.iter().enumerate() {
+            let weights = &self.settings.weights;
+            let threshold = self.settings.threshold;
+            let existing = self
+                .species
+                .iter_mut()
+                .find(|s| distance(&s.representative, tree, weights) < threshold);
+            match existing {
+                Some(s) => s.members.push(i),
+                None => self.species.push(Species {
+                    representative: tree.clone(),
+                    members: vec![i],
+                }),
+            }
+        }
+
+        self.species.retain(|s| !s.members.is_empty());
+        for s in &mut self.species {
+            s.representative = population[s.members[0]].clone();
+        }
+        &self.species
+    }
+
+    /// Speciates the scored population and breeds the next generation, with each species
+    /// having children in proportion to its shared score. Parents are chosen by binary
+    /// tournament within their species.
+    pub fn next_generation<R: Rng>(
+        &mut self,
+        population: &Population,
+        scores: &[Score],
+        mut_rate: f64,
+        mut_max: f64,
+        rng: &mut R,
+    ) -> Population {
+        self.speciate(population);
+        let shared = shared_scores(&self.species, scores);
+        let counts = offspring_counts(&self.species, &shared, population.len());
+
+        let mut next = Vec::with_capacity(population.len());
+        for (s, count) in self.species.iter().zip(counts) {
+            for _ in 0..count {
+                let a = s.members[rng.gen_range(0, s.members.len())];
+                let b = s.members[rng.gen_range(0, s.members.len())];
+                let parent = if shared[a] >= shared[b] { a } else { b };
+                let mut child = population[parent].clone();
+                child.mutate(mut_rate, mut_max);
+                next.push(child);
+            }
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use body_tree::body::def::new_cuboid;
+
+    fn shape(size: f64) -> ShapeDefinition {
+        new_cuboid((size, size, size), (0.0, 0.0, 0.0), (0.0, 0.0, 0.0))
+    }
+
+    fn tree(children: usize) -> BodyTree {
+        let mut tree = BodyTree::with_root(shape(0.5));
+        let root = tree.root();
+        for _ in 0..children {
+            tree.add_child(root, shape(0.5), Joint::Fixed);
+        }
+        tree
+    }
+
+    #[test]
+    fn distances() {
+        let weights = DistanceWeights::default();
+        assert_eq!(distance(&tree(2), &tree(2), &weights), 0.0);
+
+        // a missing child costs a node either way round
+        assert_eq!(distance(&tree(1), &tree(2), &weights), weights.node);
+        assert_eq!(distance(&tree(2), &tree(1), &weights), weights.node);
+
+        // the 3 dims of the root differ by 0.3, averaged over all 9 of its params
+        let bigger = BodyTree::with_root(shape(0.8));
+        let d = distance(&BodyTree::with_root(shape(0.5)), &bigger, &weights);
+        assert!((d - weights.shape * 0.3 * 3.0 / 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn species() {
+        let population = vec![tree(0), tree(4), tree(0), tree(4), tree(1)];
+        let mut speciation = Speciation::new(SpeciationSettings::default());
+        {
+            let species = speciation.speciate(&population);
+            let members: Vec<Vec<usize>> = species.iter().map(|s| s.members.clone()).collect();
+            assert_eq!(members, vec![vec![0, 2, 4], vec![1, 3]]);
+        }
+
+        let scores = vec![3.0, 4.0, 3.0, 4.0, 3.0];
+        let shared = shared_scores(speciation.species(), &scores);
+        assert_eq!(shared, vec![1.0, 2.0, 1.0, 2.0, 1.0]);
+
+        // 3 : 4 of 10 children
+        let counts = offspring_counts(speciation.species(), &shared, 10);
+        assert_eq!(counts, vec![4, 6]);
+
+        let mut rng = ::rand::thread_rng();
+        let next = speciation.next_generation(&population, &scores, 0.2, 0.05, &mut rng);
+        assert_eq!(next.len(), population.len());
+    }
+}
@@ -0,0 +1,336 @@
+//! Generators for the ground creatures are realised on.
+//!
+//! Everything other than the slope of the ground itself is built from static cuboids resting
+//! on it, so that rough terrain collides like any other shape.
+
+use nalgebra::{zero, Isometry3, Point3, UnitQuaternion, Vector3};
+use ncollide3d::query::{Ray, RayCast};
+use ncollide3d::shape::Cuboid;
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+
+use body_tree::Coord;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Terrain {
+    Flat,
+    /// Ground tilted about the x axis, so it rises along +z. In radians
+    Incline {
+        angle: Coord,
+    },
+    /// Square grid of columns centred on the origin
+    Heightfield {
+        seed: u64,
+        cells: usize,
+        cell_size: Coord,
+        max_height: Coord,
+    },
+    /// Steps rising along +z, starting just in front of the origin
+    Stairs {
+        steps: usize,
+        step_height: Coord,
+        step_depth: Coord,
+        width: Coord,
+    },
+    /// Randomly sized and rotated boxes scattered around the origin. Sizes range from
+    /// `MIN_OBSTACLE_SIZE` up to `max_size`, so anything smaller gives boxes of the minimum size
+    Obstacles {
+        seed: u64,
+        count: usize,
+        max_size: Coord,
+        spread: Coord,
+    },
+}
+
+/// Smallest side of an obstacle
+pub const MIN_OBSTACLE_SIZE: Coord = 0.1;
+
+/// Smallest half extent of a block's collider once its margin is taken off
+const MIN_INNER_HALF_EXTENT: Coord = 0.001;
+
+/// A static cuboid on top of the ground
+#[derive(Debug, Clone, Copy)]
+pub struct Block {
+    pub position: Isometry3<Coord>,
+    pub half_extents: Vector3<Coord>,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain::Flat
+    }
+}
+
+impl Block {
+    /// Half extents of a collider inside the given margin, which thin blocks are never made
+    /// inside out by
+    pub fn inner_half_extents(&self, margin: Coord) -> Vector3<Coord> {
+        (self.half_extents - Vector3::repeat(margin)).map(|h| h.max(MIN_INNER_HALF_EXTENT))
+    }
+}
+
+impl Terrain {
+    /// Rotation of the ground plane
+    pub fn ground_rotation(&self) -> UnitQuaternion<Coord> {
+        match self {
+            Terrain::Incline { angle } => UnitQuaternion::from_euler_angles(-angle, 0.0, 0.0),
+            _ => UnitQuaternion::identity(),
+        }
+    }
+
+    pub fn blocks(&self) -> Vec<Block> {
+        match *self {
+            Terrain::Flat | Terrain::Incline { .. } => Vec::new(),
+            Terrain::Heightfield {
+                seed,
+                cells,
+                cell_size,
+                max_height,
+            } => heightfield(seed, cells, cell_size, max_height),
+            Terrain::Stairs {
+                steps,
+                step_height,
+                step_depth,
+                width,
+            } => stairs(steps, step_height, step_depth, width),
+            Terrain::Obstacles {
+                seed,
+                count,
+                max_size,
+                spread,
+            } => obstacles(seed, count, max_size, spread),
+        }
+    }
+
+    /// Height of the highest surface directly above or below the given point, blocks included
+    pub fn height_at(&self, x: Coord, z: Coord) -> Coord {
+        self.height_among(&self.blocks(), x, z)
+    }
+
+    /// Same as `height_at`, with the terrain's blocks already generated, as generating them
+    /// is slow
+    pub fn height_among(&self, blocks: &[Block], x: Coord, z: Coord) -> Coord {
+        // the ground plane passes through the origin
+        let normal = self.ground_rotation() * Vector3::y();
+        let ground = -(normal.x * x + normal.z * z) / normal.y;
+
+        const RAY_START: Coord = 1000.0;
+        let ray = Ray::new(Point3::new(x, RAY_START, z), -Vector3::y());
+        blocks
+            .iter()
+            .filter_map(|b| Cuboid::new(b.half_extents).toi_with_ray(&b.position, &ray, true))
+            .map(|toi| RAY_START - toi)
+            .fold(ground, Coord::max)
+    }
+}
+
+fn seeded_rng(seed: u64) -> XorShiftRng {
+    // xorshift can't be seeded with zeros, so spread the seed over a non-zero pattern
+    let mut bytes = [0u8; 16];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        let shift = (i % 8) * 8;
+        *b = ((seed >> shift) as u8) ^ (0x5a + i as u8);
+    }
+    XorShiftRng::from_seed(bytes)
+}
+
+/// Uniform in `low..high`, or `low` if the range is empty
+fn gen_between<R: Rng>(rng: &mut R, low: Coord, high: Coord) -> Coord {
+    if high > low {
+        rng.gen_range(low, high)
+    } else {
+        low
+    }
+}
+
+fn block_on_ground(x: Coord, z: Coord, half_extents: Vector3<Coord>, yaw: Coord) -> Block {
+    Block {
+        position: Isometry3::new(Vector3::new(x, half_extents.y, z), Vector3::y() * yaw),
+        half_extents,
+    }
+}
+
+fn heightfield(seed: u64, cells: usize, cell_size: Coord, max_height: Coord) -> Vec<Block> {
+    let mut rng = seeded_rng(seed);
+    let raw: Vec<Coord> = (0..cells * cells).map(|_| rng.gen()).collect();
+
+    // smooth out the noise with a box blur, so neighbouring columns are similar
+    let height = |x: usize, z: usize| {
+        let mut sum = 0.0;
+        let mut count = 0.0;
+        for nx in x.saturating_sub(1)..(x + 2).min(cells) {
+            for nz in z.saturating_sub(1)..(z + 2).min(cells) {
+                sum += raw[nx * cells + nz];
+                count += 1.0;
+            }
+        }
+        sum / count * max_height
+    };
+
+    let offset = cell_size * cells as Coord / 2.0;
+    let mut blocks = Vec::with_capacity(cells * cells);
+    for x in 0..cells {
+        for z in 0..cells {
+            let h = height(x, z);
+            if h <= 0.0 {
+                continue;
+            }
+            let centre_x = (x as Coord + 0.5) * cell_size - offset;
+            let centre_z = (z as Coord + 0.5) * cell_size - offset;
+            let half = Vector3::new(cell_size / 2.0, h / 2.0, cell_size / 2.0);
+            blocks.push(block_on_ground(centre_x, centre_z, half, 0.0));
+        }
+    }
+    blocks
+}
+
+fn stairs(steps: usize, step_height: Coord, step_depth: Coord, width: Coord) -> Vec<Block> {
+    const START: Coord = 2.0;
+    (0..steps)
+        .map(|i| {
+            // each step is a column from the ground, so there's nothing to fall through
+            let height = (i + 1) as Coord * step_height;
+            let z = START + (i as Coord + 0.5) * step_depth;
+            let half = Vector3::new(width / 2.0, height / 2.0, step_depth / 2.0);
+            block_on_ground(0.0, z, half, 0.0)
+        })
+        .collect()
+}
+
+fn obstacles(seed: u64, count: usize, max_size: Coord, spread: Coord) -> Vec<Block> {
+    let mut rng = seeded_rng(seed);
+    (0..count)
+        .map(|_| {
+            let half = Vector3::new(
+                gen_between(&mut rng, MIN_OBSTACLE_SIZE, max_size) / 2.0,
+                gen_between(&mut rng, MIN_OBSTACLE_SIZE, max_size) / 2.0,
+                gen_between(&mut rng, MIN_OBSTACLE_SIZE, max_size) / 2.0,
+            );
+            let x = gen_between(&mut rng, -spread.abs(), spread.abs());
+            let z = gen_between(&mut rng, -spread.abs(), spread.abs());
+            let yaw = rng.gen_range(0.0, ::std::f64::consts::PI);
+            block_on_ground(x, z, half, yaw)
+        })
+        .collect()
+}
+
+/// Position of a ground cuboid of the given half size, so its top face passes through the
+/// origin with the given rotation
+pub fn ground_position(half_size: Coord, rotation: &UnitQuaternion<Coord>) -> Isometry3<Coord> {
+    let mut pos = Isometry3::new(Vector3::y() * -half_size, zero());
+    pos.append_rotation_mut(rotation);
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heightfield_terrain(seed: u64) -> Terrain {
+        Terrain::Heightfield {
+            seed,
+            cells: 8,
+            cell_size: 0.5,
+            max_height: 1.0,
+        }
+    }
+
+    #[test]
+    fn seeded_generators_are_deterministic() {
+        let heights =
+            |t: &Terrain| -> Vec<Coord> { t.blocks().iter().map(|b| b.half_extents.y).collect() };
+
+        assert_eq!(
+            heights(&heightfield_terrain(4)),
+            heights(&heightfield_terrain(4))
+        );
+        assert_ne!(
+            heights(&heightfield_terrain(4)),
+            heights(&heightfield_terrain(5))
+        );
+        assert!(heights(&heightfield_terrain(4))
+            .iter()
+            .all(|h| *h > 0.0 && *h <= 0.5));
+    }
+
+    #[test]
+    fn stairs_rise() {
+        let blocks = Terrain::Stairs {
+            steps: 4,
+            step_height: 0.25,
+            step_depth: 1.0,
+            width: 10.0,
+        }
+        .blocks();
+
+        assert_eq!(blocks.len(), 4);
+        for pair in blocks.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            assert!(b.half_extents.y > a.half_extents.y);
+            assert!(b.position.translation.vector.z > a.position.translation.vector.z);
+
+            // resting on the ground
+            assert!((a.position.translation.vector.y - a.half_extents.y).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn height() {
+        let stairs = Terrain::Stairs {
+            steps: 2,
+            step_height: 0.5,
+            step_depth: 1.0,
+            width: 4.0,
+        };
+        assert!(stairs.height_at(0.0, 0.0).abs() < 0.0001);
+        assert!((stairs.height_at(0.0, 2.5) - 0.5).abs() < 0.0001);
+        assert!((stairs.height_at(1.0, 3.5) - 1.0).abs() < 0.0001);
+        assert!(stairs.height_at(3.0, 3.5).abs() < 0.0001);
+        assert_eq!(
+            stairs.height_among(&stairs.blocks(), 1.0, 3.5),
+            stairs.height_at(1.0, 3.5)
+        );
+
+        let incline = Terrain::Incline {
+            angle: ::std::f64::consts::PI / 4.0,
+        };
+        assert!((incline.height_at(5.0, 2.0) - 2.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn degenerate_params() {
+        let obstacles = |max_size, spread| Terrain::Obstacles {
+            seed: 3,
+            count: 5,
+            max_size,
+            spread,
+        };
+        for blocks in &[
+            obstacles(0.05, 0.0).blocks(),
+            obstacles(MIN_OBSTACLE_SIZE, -2.0).blocks(),
+        ] {
+            assert_eq!(blocks.len(), 5);
+            for b in blocks {
+                assert_eq!(b.half_extents, Vector3::repeat(MIN_OBSTACLE_SIZE / 2.0));
+            }
+        }
+        assert!(obstacles(0.05, 0.0)
+            .blocks()
+            .iter()
+            .all(|b| b.position.translation.vector.x == 0.0));
+
+        // columns thinner than the margin
+        let blocks = Terrain::Heightfield {
+            seed: 1,
+            cells: 4,
+            cell_size: 0.01,
+            max_height: 0.01,
+        }
+        .blocks();
+        assert!(!blocks.is_empty());
+        for b in &blocks {
+            let inner = b.inner_half_extents(0.01);
+            assert!(inner.iter().all(|h| *h > 0.0));
+        }
+    }
+}
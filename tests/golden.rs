@@ -0,0 +1,230 @@
+//! Golden trajectory tests: fixed creatures are simulated for a while and the poses of their
+//! parts are compared against snapshots in `tests/snapshots`, so that any change to the
+//! physics or the realiser that alters behaviour is caught.
+//!
+//! A missing snapshot fails the test. Run with `UPDATE_SNAPSHOTS=1` to record new snapshots, or
+//! to rewrite them after an intentional change in behaviour.
+
+extern crate serde_json;
+extern crate shapes;
+
+use shapes::backend::PhysicsBackend;
+use shapes::body_tree::body::def::*;
+use shapes::body_tree::body::params::*;
+use shapes::body_tree::tree::BodyTree;
+use shapes::body_tree::Coord;
+use shapes::nphysics_backend::NPhysicsBackend;
+use shapes::physics::{PhysicalRealiser, World, DEFAULT_GROUND_CLEARANCE};
+use shapes::placement;
+use shapes::recording::{from_precise_pose, to_precise_pose, PrecisePose};
+use shapes::terrain::Terrain;
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+const TICKS: usize = 240;
+/// Poses are compared every this many ticks, to show roughly when behaviour diverged
+const CHECKPOINT_INTERVAL: usize = 60;
+const MAX_DISTANCE: Coord = 1e-3;
+const MAX_ANGLE: Coord = 1e-3;
+
+/// Pose of every part in realisation order
+type Poses = Vec<PrecisePose>;
+
+fn snapshot_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests");
+    path.push("snapshots");
+    path.push(format!("{}.json", name));
+    path
+}
+
+/// Selects the given face of the parent
+fn face(face: u32) -> f64 {
+    let mut index = FaceIndex::default();
+    index.set_face(face);
+    index.get()
+}
+
+fn motor() -> Joint {
+    Joint::Rotational {
+        torque: Torque::new(0.6),
+        max_speed: MaxSpeed::new(0.5),
+    }
+}
+
+fn single_cuboid() -> BodyTree {
+    BodyTree::with_root(new_cuboid(
+        (1.0, 0.5, 2.0),
+        (0.0, 0.0, 0.0),
+        (0.0, 0.0, 0.0),
+    ))
+}
+
+fn tilted_cuboid() -> BodyTree {
+    BodyTree::with_root(new_cuboid(
+        (1.0, 0.5, 2.0),
+        (0.0, 0.0, 0.0),
+        (0.4, 0.0, 0.3),
+    ))
+}
+
+/// A long bar with a small block at either end, so the far block is clear of the root
+fn fixed_chain() -> BodyTree {
+    let block = |pos| new_cuboid((0.2, 0.2, 0.2), pos, (0.0, 0.0, 0.0));
+    let mut tree = BodyTree::with_root(block((0.0, 0.0, 0.0)));
+    let root = tree.root();
+    let bar = tree.add_child(
+        root,
+        new_cuboid((1.0, 0.2, 0.2), (face(3), 0.5, 0.5), (0.0, 0.0, 0.0)),
+        Joint::Fixed,
+    );
+    tree.add_child(bar, block((face(2), 0.5, 0.5)), Joint::Fixed);
+    tree
+}
+
+fn hinged_legs() -> BodyTree {
+    let mut tree = single_cuboid();
+    let root = tree.root();
+    for side in &[0, 1] {
+        tree.add_child(
+            root,
+            new_cuboid((0.3, 1.0, 0.3), (face(*side), 0.0, 0.0), (0.0, 0.0, 0.0)),
+            motor(),
+        );
+    }
+    tree
+}
+
+fn stairs() -> Terrain {
+    Terrain::Stairs {
+        steps: 5,
+        step_height: 0.15,
+        step_depth: 1.0,
+        width: 20.0,
+    }
+}
+
+/// Poses of every part at each checkpoint
+fn simulate<B: PhysicsBackend>(tree: &BodyTree, terrain: Terrain) -> Vec<Poses> {
+    assert!(
+        placement::overlaps(tree).is_empty(),
+        "fixture has overlapping parts"
+    );
+
+    let mut world = World::<B>::default();
+    world.set_terrain(terrain);
+    {
+        let mut r = PhysicalRealiser::new(&mut world);
+        r.ground_clearance = Some(DEFAULT_GROUND_CLEARANCE);
+        r.realise(tree);
+    }
+
+    let poses = |world: &World<B>| -> Poses {
+        world
+            .colliders()
+            .filter(|(_, obj, _, _)| obj.creature.is_some())
+            .map(|(_, _, pos, _)| to_precise_pose(&pos))
+            .collect()
+    };
+
+    let mut checkpoints = Vec::new();
+    for tick in 1..=TICKS {
+        world.tick();
+        if tick % CHECKPOINT_INTERVAL == 0 {
+            checkpoints.push(poses(&world));
+        }
+    }
+    checkpoints
+}
+
+fn check_against_snapshot(name: &str, checkpoints: &[Poses]) {
+    let path = snapshot_path(name);
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+    if update {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let f = File::create(&path).expect("failed to create snapshot");
+        serde_json::to_writer_pretty(f, &checkpoints).unwrap();
+        return;
+    }
+
+    assert!(
+        path.exists(),
+        "{}: no snapshot at {:?}, record it with UPDATE_SNAPSHOTS=1",
+        name,
+        path
+    );
+    let f = File::open(&path).expect("failed to open snapshot");
+    let expected: Vec<Poses> = serde_json::from_reader(f).expect("bad snapshot");
+    assert_eq!(
+        expected.len(),
+        checkpoints.len(),
+        "{}: checkpoint count",
+        name
+    );
+
+    for (i, (expected, actual)) in expected.iter().zip(checkpoints).enumerate() {
+        let tick = (i + 1) * CHECKPOINT_INTERVAL;
+        assert_eq!(expected.len(), actual.len(), "{}: part count", name);
+        for (part, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+            let (expected, actual) = (from_precise_pose(expected), from_precise_pose(actual));
+            let distance = (expected.translation.vector - actual.translation.vector).norm();
+            let angle = expected.rotation.angle_to(&actual.rotation);
+            assert!(
+                distance <= MAX_DISTANCE && angle <= MAX_ANGLE,
+                "{}: part {} diverged by {} and {} rad at tick {}",
+                name,
+                part,
+                distance,
+                angle,
+                tick
+            );
+        }
+    }
+}
+
+fn golden<B: PhysicsBackend>(name: &str, tree: BodyTree, terrain: Terrain) {
+    let checkpoints = simulate::<B>(&tree, terrain);
+    check_against_snapshot(name, &checkpoints);
+}
+
+#[test]
+fn deterministic() {
+    let tree = hinged_legs();
+    assert_eq!(
+        simulate::<NPhysicsBackend>(&tree, Terrain::Flat),
+        simulate::<NPhysicsBackend>(&tree, Terrain::Flat)
+    );
+}
+
+#[test]
+fn golden_single_cuboid() {
+    golden::<NPhysicsBackend>("single_cuboid", single_cuboid(), Terrain::Flat);
+}
+
+#[test]
+fn golden_tilted_cuboid() {
+    golden::<NPhysicsBackend>("tilted_cuboid", tilted_cuboid(), Terrain::Flat);
+}
+
+#[test]
+fn golden_fixed_chain() {
+    golden::<NPhysicsBackend>("fixed_chain", fixed_chain(), Terrain::Flat);
+}
+
+#[test]
+fn golden_hinged_legs() {
+    golden::<NPhysicsBackend>("hinged_legs", hinged_legs(), Terrain::Flat);
+}
+
+#[test]
+fn golden_hinged_legs_on_stairs() {
+    golden::<NPhysicsBackend>("hinged_legs_stairs", hinged_legs(), stairs());
+}
+
+#[cfg(feature = "rapier")]
+#[test]
+fn golden_rapier_hinged_legs() {
+    use shapes::rapier_backend::RapierBackend;
+    golden::<RapierBackend>("rapier_hinged_legs", hinged_legs(), Terrain::Flat);
+}
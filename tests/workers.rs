@@ -0,0 +1,45 @@
+extern crate shapes;
+
+use shapes::body_tree::{tree, Population};
+use shapes::evaluate::{self, EvaluationSettings};
+use shapes::remote;
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+fn spawn_worker() -> (Child, TcpStream) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_worker"))
+        .arg("tcp")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to start worker");
+
+    let mut line = String::new();
+    BufReader::new(child.stdout.as_mut().unwrap())
+        .read_line(&mut line)
+        .expect("worker didn't announce itself");
+    let addr = line.trim().trim_start_matches("listening on ");
+    let stream = TcpStream::connect(addr).expect("failed to connect to worker");
+    (child, stream)
+}
+
+#[test]
+fn worker_processes() {
+    let pop: Population = (0..6).map(|_| tree::grow_random_tree(2)).collect();
+    let settings = EvaluationSettings {
+        ticks: 30,
+        ..EvaluationSettings::default()
+    };
+
+    let (children, workers): (Vec<_>, Vec<_>) = (0..3).map(|_| spawn_worker()).unzip();
+    let controls: Vec<TcpStream> = workers.iter().map(|w| w.try_clone().unwrap()).collect();
+
+    let scores = remote::evaluate_population(workers, &pop, &settings).expect("remote failed");
+    assert_eq!(scores, evaluate::evaluate_population(&pop, &settings));
+
+    for (mut control, mut child) in controls.into_iter().zip(children) {
+        remote::shutdown(&mut control).unwrap();
+        drop(control);
+        assert!(child.wait().unwrap().success());
+    }
+}